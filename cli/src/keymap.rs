@@ -0,0 +1,210 @@
+use core::gameboy::KeyStates;
+use minifb::Key;
+
+// Maps every Game Boy button to a keyboard key.
+//
+// Loaded from a flat TOML file, buttons left out keep their default:
+//
+// a = "Z"
+// b = "X"
+// start = "Enter"
+// select = "RightShift"
+// up = "Up"
+// down = "Down"
+// left = "Left"
+// right = "Right"
+pub struct KeyMap {
+    pub a: Key,
+    pub b: Key,
+    pub start: Key,
+    pub select: Key,
+    pub up: Key,
+    pub down: Key,
+    pub left: Key,
+    pub right: Key,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap {
+            a: Key::Z,
+            b: Key::X,
+            start: Key::Enter,
+            select: Key::RightShift,
+            up: Key::Up,
+            down: Key::Down,
+            left: Key::Left,
+            right: Key::Right,
+        }
+    }
+}
+
+impl KeyMap {
+    pub fn load(path: &str) -> Result<KeyMap, String> {
+        let config =
+            std::fs::read_to_string(path).map_err(|err| format!("failed to read {path}: {err}"))?;
+        KeyMap::parse(&config).map_err(|err| format!("{path}: {err}"))
+    }
+
+    pub fn parse(config: &str) -> Result<KeyMap, String> {
+        let mut map = KeyMap::default();
+
+        for (i, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((button, key)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `button = \"Key\"`", i + 1));
+            };
+            let button = button.trim();
+            let key = key.trim().trim_matches('"');
+            let key = key_from_name(key)
+                .ok_or_else(|| format!("line {}: unknown key \"{key}\"", i + 1))?;
+
+            match button {
+                "a" => map.a = key,
+                "b" => map.b = key,
+                "start" => map.start = key,
+                "select" => map.select = key,
+                "up" => map.up = key,
+                "down" => map.down = key,
+                "left" => map.left = key,
+                "right" => map.right = key,
+                _ => return Err(format!("line {}: unknown button \"{button}\"", i + 1)),
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn key_states(&self, keys: &[Key]) -> KeyStates {
+        KeyStates {
+            a: keys.contains(&self.a),
+            b: keys.contains(&self.b),
+            start: keys.contains(&self.start),
+            select: keys.contains(&self.select),
+            up: keys.contains(&self.up),
+            down: keys.contains(&self.down),
+            left: keys.contains(&self.left),
+            right: keys.contains(&self.right),
+        }
+    }
+}
+
+// Keys are named after their minifb variant, case insensitive.
+fn key_from_name(name: &str) -> Option<Key> {
+    KEYS.iter()
+        .copied()
+        .find(|key| format!("{key:?}").eq_ignore_ascii_case(name))
+}
+
+const KEYS: [Key; 106] = [
+    Key::Key0,
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::F13,
+    Key::F14,
+    Key::F15,
+    Key::Down,
+    Key::Left,
+    Key::Right,
+    Key::Up,
+    Key::Apostrophe,
+    Key::Backquote,
+    Key::Backslash,
+    Key::Comma,
+    Key::Equal,
+    Key::LeftBracket,
+    Key::Minus,
+    Key::Period,
+    Key::RightBracket,
+    Key::Semicolon,
+    Key::Slash,
+    Key::Backspace,
+    Key::Delete,
+    Key::End,
+    Key::Enter,
+    Key::Escape,
+    Key::Home,
+    Key::Insert,
+    Key::Menu,
+    Key::PageDown,
+    Key::PageUp,
+    Key::Pause,
+    Key::Space,
+    Key::Tab,
+    Key::NumLock,
+    Key::CapsLock,
+    Key::ScrollLock,
+    Key::LeftShift,
+    Key::RightShift,
+    Key::LeftCtrl,
+    Key::RightCtrl,
+    Key::NumPad0,
+    Key::NumPad1,
+    Key::NumPad2,
+    Key::NumPad3,
+    Key::NumPad4,
+    Key::NumPad5,
+    Key::NumPad6,
+    Key::NumPad7,
+    Key::NumPad8,
+    Key::NumPad9,
+    Key::NumPadDot,
+    Key::NumPadSlash,
+    Key::NumPadAsterisk,
+    Key::NumPadMinus,
+    Key::NumPadPlus,
+    Key::NumPadEnter,
+    Key::LeftAlt,
+    Key::RightAlt,
+    Key::LeftSuper,
+    Key::RightSuper,
+];
@@ -1,6 +1,8 @@
+mod keymap;
+
 use core::cart::Cart;
 use core::gameboy::GameBoy;
-use core::gameboy::KeyStates;
+use keymap::KeyMap;
 use minifb::Key;
 use minifb::Window;
 use minifb::WindowOptions;
@@ -10,6 +12,14 @@ use std::io::BufWriter;
 use std::io::Write;
 use std::path::Path;
 use std::process;
+use std::time::{Duration, Instant};
+
+const TARGET_FPS: usize = 60;
+const TURBO_KEY: Key = Key::Space;
+const TURBO_FRAME_SKIP: usize = 8; // Emulated frames per presented frame in turbo
+const PAUSE_KEY: Key = Key::P;
+const STEP_KEY: Key = Key::N; // Advances one frame while paused
+const SAVE_INTERVAL: Duration = Duration::from_secs(5); // Battery save checkpoints, in case we crash
 
 fn main() {
     let mut args = env::args();
@@ -17,30 +27,34 @@ fn main() {
     let rom_path = match args.next() {
         Some(path) => path,
         None => {
-            eprintln!("usage: {program} <rom.gb>");
+            eprintln!("usage: {program} <rom.gb> [keymap.toml]");
             process::exit(2);
         }
     };
 
-    let rom = match std::fs::read(&rom_path) {
-        Ok(data) => data,
-        Err(err) => {
-            eprintln!("failed to read rom {rom_path}: {err}");
+    let key_map = match args.next() {
+        Some(path) => KeyMap::load(&path).unwrap_or_else(|err| {
+            eprintln!("failed to load key map: {err}");
             process::exit(1);
-        }
+        }),
+        None => KeyMap::default(),
     };
 
-    let save_data = load_save_file(&rom_path);
-
-    let cart = match Cart::from_bytes(rom, save_data) {
+    let cart = match Cart::from_path(&rom_path) {
         Ok(cart) => cart,
         Err(err) => {
-            eprintln!("failed to parse rom header: {err}");
+            eprintln!("failed to load rom {rom_path}: {err}");
             process::exit(1);
         }
     };
 
     let title = cart.get_title();
+    println!("Booted ROM: {title}");
+    println!("{}", cart.header);
+    if let Err(err) = cart.verify_header() {
+        println!("Warning: {err}, the real hardware would not boot this ROM");
+    }
+
     let mut gameboy = GameBoy::new(cart);
 
     const WIDTH: usize = 160;
@@ -55,41 +69,66 @@ fn main() {
         panic!("{}", e);
     });
 
-    window.set_target_fps(60);
+    window.set_target_fps(TARGET_FPS);
+    let mut turbo = false;
+    let mut paused = false;
+    let save_path = Cart::save_path(&rom_path);
+    let mut last_checkpoint = Instant::now();
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        let keys = build_key_state(&window.get_keys());
-        gameboy.run_frame(keys);
+        // Holding the turbo key lifts the fps cap and only presents every few frames.
+        let turbo_held = window.is_key_down(TURBO_KEY);
+        if turbo_held != turbo {
+            turbo = turbo_held;
+            window.set_target_fps(if turbo { 0 } else { TARGET_FPS });
+        }
+        let frames = if turbo { TURBO_FRAME_SKIP } else { 1 };
+
+        if window.is_key_pressed(PAUSE_KEY, minifb::KeyRepeat::No) {
+            paused = !paused;
+        }
+        let frames = if !paused {
+            frames
+        } else if window.is_key_pressed(STEP_KEY, minifb::KeyRepeat::No) {
+            1
+        } else {
+            0
+        };
+
+        let keys = key_map.key_states(&window.get_keys());
+        if let Err(err) = gameboy.run_frames(frames, keys) {
+            eprintln!("emulation stopped: {err}");
+            break;
+        }
 
-        let fb = gameboy.get_last_frame_buffer();
+        let mut fb = gameboy.get_last_frame_buffer();
 
         if window.is_key_pressed(Key::S, minifb::KeyRepeat::No) {
             dump_framebuffer_ppm("screenshot.ppm", &fb).unwrap();
         }
 
+        // Dim the screen while paused, the window keeps updating to stay responsive.
+        if paused {
+            for color in fb.iter_mut() {
+                *color = (*color >> 1) & 0x7F7F7F;
+            }
+        }
+
         window.update_with_buffer(&fb, WIDTH, HEIGHT).unwrap();
-    }
 
-    if let Some(save_data) = gameboy.save() {
-        save_to_file(save_data, &rom_path).expect("Failed to created save file");
+        if last_checkpoint.elapsed() >= SAVE_INTERVAL {
+            last_checkpoint = Instant::now();
+            if gameboy.take_dirty_save().is_some()
+                && let Err(err) = gameboy.save_to_path(&save_path)
+            {
+                eprintln!("failed to write save file: {err}");
+            }
+        }
     }
-}
-
-pub fn build_save_path(rom_path: &str) -> String {
-    let name = rom_path.rsplit_once(".").unwrap().0;
-    format!("{name}.sav")
-}
-
-pub fn load_save_file(rom_path: &str) -> Option<Vec<u8>> {
-    std::fs::read(build_save_path(rom_path)).ok()
-}
 
-pub fn save_to_file(data: Vec<u8>, rom_path: &str) -> std::io::Result<()> {
-    let mut file = File::create(build_save_path(rom_path))?;
-    file.write_all(&data)?;
-    file.flush()?;
-
-    Ok(())
+    if let Err(err) = gameboy.save_to_path(&save_path) {
+        eprintln!("failed to write save file: {err}");
+    }
 }
 
 pub fn dump_framebuffer_ppm<P: AsRef<Path>>(path: P, fb: &[u32; 160 * 144]) -> std::io::Result<()> {
@@ -111,16 +150,3 @@ pub fn dump_framebuffer_ppm<P: AsRef<Path>>(path: P, fb: &[u32; 160 * 144]) -> s
     w.flush()?;
     Ok(())
 }
-
-fn build_key_state(keys: &[Key]) -> KeyStates {
-    KeyStates {
-        a: keys.contains(&Key::Z),
-        b: keys.contains(&Key::X),
-        start: keys.contains(&Key::Enter),
-        select: keys.contains(&Key::RightShift),
-        up: keys.contains(&Key::Up),
-        down: keys.contains(&Key::Down),
-        left: keys.contains(&Key::Left),
-        right: keys.contains(&Key::Right),
-    }
-}
@@ -0,0 +1,91 @@
+use core::{
+    cart::Cart,
+    cpu::CPU,
+    gameboy::{Buttons, GameBoy, Model},
+    mmu::MMU,
+};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+const INSTRUCTIONS: u64 = 10_000;
+
+// A mix of loads, ALU, CB, stack and jump opcodes, looped from 0x0150.
+const LOOP_BODY: &[u8] = &[
+    0x7E, // LD A,(HL)
+    0x23, // INC HL
+    0x80, // ADD A,B
+    0xCB, 0x37, // SWAP A
+    0x0C, // INC C
+    0x15, // DEC D
+    0xAB, // XOR E
+    0x47, // LD B,A
+    0xC5, // PUSH BC
+    0xD1, // POP DE
+    0x06, 0x5A, // LD B,$5A
+    0xEA, 0x00, 0xC0, // LD ($C000),A
+    0x3C, // INC A
+    0x28, 0x00, // JR Z,+0
+];
+
+fn loop_rom() -> Vec<u8> {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP $0150
+
+    let mut code = vec![0x21, 0x00, 0x40]; // LD HL,$4000
+    for _ in 0..16 {
+        code.extend_from_slice(LOOP_BODY);
+    }
+    code.extend_from_slice(&[0xC3, 0x50, 0x01]); // JP $0150
+    rom[0x150..0x150 + code.len()].copy_from_slice(&code);
+    rom
+}
+
+fn cpu_step(c: &mut Criterion) {
+    let mut cpu = CPU::init(Model::DMG);
+    let mut mmu = MMU::new(Cart::from_bytes(loop_rom(), None).unwrap(), false);
+
+    let mut group = c.benchmark_group("cpu");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function("step", |b| {
+        b.iter(|| {
+            for _ in 0..INSTRUCTIONS {
+                cpu.step(&mut mmu).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+// Background, window and a full OAM on screen, while the CPU idles in a JR loop.
+fn ppu_frame(c: &mut Criterion) {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+    let mut gameboy = GameBoy::new(Cart::from_bytes(rom, None).unwrap());
+
+    for addr in 0x8000..0x9800u16 {
+        gameboy.write_byte(addr, (addr as u8).wrapping_mul(0x1D));
+    }
+    for addr in 0x9800..0xA000u16 {
+        gameboy.write_byte(addr, addr as u8);
+    }
+    for sprite in 0..40u16 {
+        let oam = 0xFE00 + sprite * 4;
+        gameboy.write_byte(oam, 16 + (sprite / 10) as u8 * 36); // 10 sprites per row
+        gameboy.write_byte(oam + 1, 8 + (sprite % 10) as u8 * 16);
+        gameboy.write_byte(oam + 2, sprite as u8);
+        gameboy.write_byte(oam + 3, (sprite as u8 & 0x03) << 5);
+    }
+    gameboy.write_byte(0xFF4A, 0x40); // WY
+    gameboy.write_byte(0xFF4B, 0x57); // WX
+    gameboy.write_byte(0xFF40, 0xF3); // LCD, window at $9C00, $8000 tiles, OBJ, BG
+
+    let mut group = c.benchmark_group("ppu");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("frame", |b| {
+        b.iter(|| gameboy.run_frame(Buttons::NONE).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, cpu_step, ppu_frame);
+criterion_main!(benches);
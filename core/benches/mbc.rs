@@ -0,0 +1,46 @@
+use core::{
+    cart::Cart,
+    gameboy::{Buttons, GameBoy},
+};
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+
+// 128 KiB ROM with bank 3 mapped, so every read goes through the switchable bank.
+fn banked_cart(cartridge_type: u8) -> Cart {
+    let mut rom = vec![0u8; 0x20000];
+    rom[0x147] = cartridge_type;
+    rom[0x148] = 0x02;
+    let mut cart = Cart::from_bytes(rom, None).unwrap();
+    cart.wb(0x2000, 0x03);
+    cart
+}
+
+fn rom_bank_reads(c: &mut Criterion) {
+    for (name, cartridge_type) in [("mbc1", 0x01), ("mbc3", 0x11), ("mbc5", 0x19)] {
+        let cart = banked_cart(cartridge_type);
+        c.bench_function(&format!("{name}_rom_bank_reads"), |b| {
+            b.iter(|| {
+                let mut sum = 0u8;
+                for addr in 0x4000..=0x7FFF {
+                    sum = sum.wrapping_add(cart.rb(black_box(addr)));
+                }
+                sum
+            })
+        });
+    }
+}
+
+// LD HL,$4000; loop: LD A,(HL+); JR loop — the CPU spends the whole frame fetching.
+fn fetch_loop_frame(c: &mut Criterion) {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x147] = 0x01;
+    rom[0x100..0x106].copy_from_slice(&[0x21, 0x00, 0x40, 0x2A, 0x18, 0xFD]);
+    let mut gameboy = GameBoy::new(Cart::from_bytes(rom, None).unwrap());
+
+    c.bench_function("mbc1_fetch_loop_frame", |b| {
+        b.iter(|| gameboy.run_frame(Buttons::NONE))
+    });
+}
+
+criterion_group!(benches, rom_bank_reads, fetch_loop_frame);
+criterion_main!(benches);
@@ -0,0 +1,814 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{
+    mmu::TCycles,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub const NR10_ADDR: u16 = 0xFF10; // [0xFF10 - 0xFF14] — Channel 1
+const NR11_ADDR: u16 = 0xFF11;
+const NR14_ADDR: u16 = 0xFF14;
+const NR21_ADDR: u16 = 0xFF16; // [0xFF16 - 0xFF19] — Channel 2, there is no NR20
+const NR24_ADDR: u16 = 0xFF19;
+const NR30_ADDR: u16 = 0xFF1A; // [0xFF1A - 0xFF1E] — Channel 3
+const NR31_ADDR: u16 = 0xFF1B;
+const NR34_ADDR: u16 = 0xFF1E;
+const NR50_ADDR: u16 = 0xFF24;
+const NR51_ADDR: u16 = 0xFF25;
+pub const NR52_ADDR: u16 = 0xFF26;
+pub const WAVE_RAM_START: u16 = 0xFF30;
+pub const WAVE_RAM_END: u16 = 0xFF3F;
+
+const FRAME_SEQUENCER_PERIOD: TCycles = 8192; // 512 Hz
+const SAMPLE_PERIOD: TCycles = 4; // One sample per M-cycle
+const NATIVE_SAMPLE_RATE: u32 = 4_194_304 / SAMPLE_PERIOD; // ~1.05 MHz
+const SINK_CHUNK_LEN: usize = 1024; // Stereo samples handed to the sink at once
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+pub struct APU {
+    ch1: Pulse,
+    ch2: Pulse,
+    ch3: Wave,
+    power: bool, // [0xFF26] — NR52 bit 7, the channel status bits come from the channels themselves
+    nr50: u8,    // [0xFF24] — Master volume [ 7 6 5 4 3 2 1 0 ] 6-4: Left volume 2-0: Right volume
+    nr51: u8, // [0xFF25] — Sound panning [ 7 6 5 4 3 2 1 0 ] 7-4: Channel 4-1 left 3-0: Channel 4-1 right
+    frame_sequencer: u8, // Step 0-7 of the 512 Hz frame sequencer
+    frame_sequencer_acc: TCycles, // Cycles until the next frame sequencer step
+    sample_acc: TCycles,
+    output: Option<Resampler>,
+    capture: Option<Resampler>, // Second sink, recording alongside the live output
+    muted: u8,                  // Host side mask, bit 0-3: channel 1-4 left out of the mix
+}
+
+// Receives the mixed audio, as interleaved left / right samples in -1.0..1.0.
+pub trait AudioSink {
+    fn push_samples(&mut self, samples: &[f32]);
+}
+
+// Frame sequencer
+//
+// Step   Length Ctr  Vol Env     Sweep
+// ---------------------------------------
+// 0      Clock       -           -
+// 1      -           -           -
+// 2      Clock       -           Clock
+// 3      -           -           -
+// 4      Clock       -           -
+// 5      -           -           -
+// 6      Clock       -           Clock
+// 7      -           Clock       -
+
+// NR52 [ 7 - - - 3 2 1 0 ]
+// 7 - Audio on/off: turning it off clears every register but wave RAM and the DMG length timers
+// 3 - Channel 4 on [read-only]
+// 2 - Channel 3 on [read-only]
+// 1 - Channel 2 on [read-only]
+// 0 - Channel 1 on [read-only]
+
+impl APU {
+    pub fn new() -> Self {
+        // This is the register state after the DMG Bios has run, the boot sound
+        // left channel 1 running with its volume faded out.
+        // ref: [https://gbdev.io/pandocs/Power_Up_Sequence.html]
+        let mut ch1 = Pulse::new(true);
+        ch1.enabled = true;
+        ch1.duty = 0x02;
+        ch1.envelope = 0xF3;
+
+        APU {
+            ch1,
+            ch2: Pulse::new(false),
+            ch3: Wave::new(),
+            power: true,
+            nr50: 0x77,
+            nr51: 0xF3,
+            frame_sequencer: 0,
+            frame_sequencer_acc: FRAME_SEQUENCER_PERIOD,
+            sample_acc: 0,
+            output: None,
+            capture: None,
+            muted: 0,
+        }
+    }
+
+    // Power cycle, the sinks and the muted channels stay as they are.
+    pub fn reset(&mut self) {
+        let output = self.output.take();
+        let capture = self.capture.take();
+        let muted = self.muted;
+        *self = APU::new();
+        self.output = output;
+        self.capture = capture;
+        self.muted = muted;
+    }
+
+    // Only changes what reaches the mix, the channel keeps running and NR52 still reports it.
+    pub fn set_channel_enabled(&mut self, channel: u8, enabled: bool) {
+        if !(1..=4).contains(&channel) {
+            return;
+        }
+        let bit = 1 << (channel - 1);
+        if enabled {
+            self.muted &= !bit;
+        } else {
+            self.muted |= bit;
+        }
+    }
+
+    // Output is resampled down to `sample_rate` before reaching the sink.
+    pub fn set_sink(&mut self, sink: Box<dyn AudioSink>, sample_rate: u32) {
+        self.output = Some(Resampler::new(sink, sample_rate));
+    }
+
+    pub fn clear_sink(&mut self) {
+        if let Some(output) = self.output.as_mut() {
+            output.flush();
+        }
+        self.output = None;
+    }
+
+    pub fn set_capture(&mut self, sink: Box<dyn AudioSink>, sample_rate: u32) {
+        self.capture = Some(Resampler::new(sink, sample_rate));
+    }
+
+    pub fn clear_capture(&mut self) {
+        if let Some(capture) = self.capture.as_mut() {
+            capture.flush();
+        }
+        self.capture = None;
+    }
+
+    // Hands everything generated so far to the sinks.
+    pub fn flush(&mut self) {
+        if let Some(output) = self.output.as_mut() {
+            output.flush();
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            capture.flush();
+        }
+    }
+
+    pub fn rb(&self, addr: u16) -> u8 {
+        match addr {
+            NR10_ADDR..=NR14_ADDR => self.ch1.rb(addr - NR10_ADDR),
+            NR21_ADDR..=NR24_ADDR => self.ch2.rb(addr - NR21_ADDR + 1),
+            NR30_ADDR..=NR34_ADDR => self.ch3.rb(addr - NR30_ADDR),
+            NR50_ADDR => self.nr50,
+            NR51_ADDR => self.nr51,
+            NR52_ADDR => {
+                (self.power as u8) << 7
+                    | 0x70
+                    | (self.ch3.enabled as u8) << 2
+                    | (self.ch2.enabled as u8) << 1
+                    | self.ch1.enabled as u8
+            }
+            WAVE_RAM_START..=WAVE_RAM_END => self.ch3.ram[(addr - WAVE_RAM_START) as usize],
+            _ => 0xFF, // Unimplemented
+        }
+    }
+
+    pub fn wb(&mut self, addr: u16, value: u8) {
+        if !self.power {
+            // Only NR52, wave RAM and, on DMG, the length timers stay writable.
+            match addr {
+                NR11_ADDR => self.ch1.write_length(value),
+                NR21_ADDR => self.ch2.write_length(value),
+                NR31_ADDR => self.ch3.write_length(value),
+                NR52_ADDR => self.set_power(value & 0x80 != 0),
+                WAVE_RAM_START..=WAVE_RAM_END => {
+                    self.ch3.ram[(addr - WAVE_RAM_START) as usize] = value
+                }
+                _ => (),
+            }
+            return;
+        }
+
+        match addr {
+            NR10_ADDR..=NR14_ADDR => self.ch1.wb(addr - NR10_ADDR, value),
+            NR21_ADDR..=NR24_ADDR => self.ch2.wb(addr - NR21_ADDR + 1, value),
+            NR30_ADDR..=NR34_ADDR => self.ch3.wb(addr - NR30_ADDR, value),
+            NR50_ADDR => self.nr50 = value,
+            NR51_ADDR => self.nr51 = value,
+            NR52_ADDR => self.set_power(value & 0x80 != 0),
+            WAVE_RAM_START..=WAVE_RAM_END => self.ch3.ram[(addr - WAVE_RAM_START) as usize] = value,
+            _ => (), // Unimplemented
+        }
+    }
+
+    fn set_power(&mut self, on: bool) {
+        if on && !self.power {
+            self.frame_sequencer = 0;
+            self.frame_sequencer_acc = FRAME_SEQUENCER_PERIOD;
+        } else if !on && self.power {
+            self.ch1.power_off();
+            self.ch2.power_off();
+            self.ch3.power_off();
+            self.nr50 = 0;
+            self.nr51 = 0;
+        }
+        self.power = on;
+    }
+
+    pub fn tick(&mut self, cycles: TCycles) {
+        if self.power {
+            self.ch1.tick(cycles);
+            self.ch2.tick(cycles);
+            self.ch3.tick(cycles);
+
+            if self.frame_sequencer_acc <= cycles {
+                self.frame_sequencer_acc += FRAME_SEQUENCER_PERIOD;
+                self.step_frame_sequencer();
+            }
+            self.frame_sequencer_acc -= cycles;
+        }
+
+        self.sample_acc += cycles;
+        while self.sample_acc >= SAMPLE_PERIOD {
+            self.sample_acc -= SAMPLE_PERIOD;
+            // Nobody is listening, skip mixing entirely.
+            if self.output.is_some() || self.capture.is_some() {
+                let (left, right) = self.mix();
+                if let Some(output) = self.output.as_mut() {
+                    output.push(left, right);
+                }
+                if let Some(capture) = self.capture.as_mut() {
+                    capture.push(left, right);
+                }
+            }
+        }
+    }
+
+    fn step_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer;
+        self.frame_sequencer = (self.frame_sequencer + 1) & 0x07;
+
+        if step & 0x01 == 0 {
+            self.ch1.clock_length();
+            self.ch2.clock_length();
+            self.ch3.clock_length();
+        }
+        if step == 2 || step == 6 {
+            self.ch1.clock_sweep();
+        }
+        if step == 7 {
+            self.ch1.clock_envelope();
+            self.ch2.clock_envelope();
+        }
+    }
+
+    // Sums the channels routed to each side by NR51, scaled by the NR50 master volume.
+    fn mix(&self) -> (f32, f32) {
+        let outputs = [
+            self.ch1.dac_output(),
+            self.ch2.dac_output(),
+            self.ch3.dac_output(),
+        ];
+
+        let (mut left, mut right) = (0.0, 0.0);
+        for (i, output) in outputs.into_iter().enumerate() {
+            if self.muted & (1 << i) != 0 {
+                continue;
+            }
+            if self.nr51 & (0x10 << i) != 0 {
+                left += output;
+            }
+            if self.nr51 & (0x01 << i) != 0 {
+                right += output;
+            }
+        }
+
+        let left_volume = (((self.nr50 >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((self.nr50 & 0x07) + 1) as f32 / 8.0;
+
+        // Four channels at full scale fit in -1.0..1.0
+        (left * left_volume / 4.0, right * right_volume / 4.0)
+    }
+}
+
+// Averages the native rate output down to the sink's sample rate.
+struct Resampler {
+    sink: Box<dyn AudioSink>,
+    sample_rate: u32,
+    phase: u32, // Advances by sample_rate per input sample, emits on NATIVE_SAMPLE_RATE
+    left: f32,
+    right: f32,
+    count: u32,
+    buffer: Vec<f32>,
+}
+
+impl Resampler {
+    fn new(sink: Box<dyn AudioSink>, sample_rate: u32) -> Self {
+        Resampler {
+            sink,
+            sample_rate: sample_rate.clamp(1, NATIVE_SAMPLE_RATE),
+            phase: 0,
+            left: 0.0,
+            right: 0.0,
+            count: 0,
+            buffer: Vec::with_capacity(SINK_CHUNK_LEN * 2),
+        }
+    }
+
+    fn push(&mut self, left: f32, right: f32) {
+        self.left += left;
+        self.right += right;
+        self.count += 1;
+
+        self.phase += self.sample_rate;
+        if self.phase < NATIVE_SAMPLE_RATE {
+            return;
+        }
+        self.phase -= NATIVE_SAMPLE_RATE;
+
+        let count = self.count as f32;
+        self.buffer.push(self.left / count);
+        self.buffer.push(self.right / count);
+        self.left = 0.0;
+        self.right = 0.0;
+        self.count = 0;
+
+        if self.buffer.len() >= SINK_CHUNK_LEN * 2 {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            self.sink.push_samples(&self.buffer);
+            self.buffer.clear();
+        }
+    }
+}
+
+// Square wave channel, channel 1 additionally has a frequency sweep.
+//
+// NRx0 [ - 6 5 4 3 2 1 0 ] 6-4: Sweep pace 3: Direction (1 = decrease) 2-0: Step    [Channel 1 only]
+// NRx1 [ 7 6 5 4 3 2 1 0 ] 7-6: Wave duty 5-0: Initial length timer [write-only]
+// NRx2 [ 7 6 5 4 3 2 1 0 ] 7-4: Initial volume 3: Envelope direction (1 = increase) 2-0: Envelope pace
+// NRx3 [ 7 6 5 4 3 2 1 0 ] Period low [write-only]
+// NRx4 [ 7 6 - - - 2 1 0 ] 7: Trigger 6: Length enable 2-0: Period high [write-only]
+struct Pulse {
+    enabled: bool,
+    sweep: Option<Sweep>,
+    duty: u8,
+    duty_pos: u8,
+    length_counter: u8,
+    length_enable: bool,
+    envelope: u8, // Raw NRx2
+    volume: u8,
+    envelope_timer: u8,
+    frequency: u16,
+    frequency_timer: TCycles,
+}
+
+struct Sweep {
+    nr10: u8,
+    timer: u8,
+    shadow_frequency: u16,
+    enabled: bool,
+}
+
+impl Pulse {
+    fn new(has_sweep: bool) -> Self {
+        Pulse {
+            enabled: false,
+            sweep: has_sweep.then_some(Sweep {
+                nr10: 0,
+                timer: 0,
+                shadow_frequency: 0,
+                enabled: false,
+            }),
+            duty: 0,
+            duty_pos: 0,
+            length_counter: 0,
+            length_enable: false,
+            envelope: 0,
+            volume: 0,
+            envelope_timer: 0,
+            frequency: 0,
+            frequency_timer: 2048 * 4,
+        }
+    }
+
+    fn rb(&self, reg: u16) -> u8 {
+        match reg {
+            0 => self.sweep.as_ref().map_or(0xFF, |s| s.nr10 | 0x80),
+            1 => self.duty << 6 | 0x3F,
+            2 => self.envelope,
+            3 => 0xFF,
+            4 => (self.length_enable as u8) << 6 | 0xBF,
+            _ => unreachable!(),
+        }
+    }
+
+    fn wb(&mut self, reg: u16, value: u8) {
+        match reg {
+            0 => {
+                if let Some(sweep) = self.sweep.as_mut() {
+                    sweep.nr10 = value & 0x7F;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.write_length(value);
+            }
+            2 => {
+                self.envelope = value;
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x07) << 8);
+                self.length_enable = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_length(&mut self, value: u8) {
+        self.length_counter = 64 - (value & 0x3F);
+    }
+
+    fn power_off(&mut self) {
+        *self = Pulse {
+            length_counter: self.length_counter,
+            ..Pulse::new(self.sweep.is_some())
+        };
+    }
+
+    fn dac_enabled(&self) -> bool {
+        self.envelope & 0xF8 != 0
+    }
+
+    fn period(&self) -> TCycles {
+        (2048 - self.frequency as TCycles) * 4
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        if self.length_counter == 0 {
+            self.length_counter = 64;
+        }
+        self.frequency_timer = self.period();
+        self.volume = self.envelope >> 4;
+        self.envelope_timer = self.envelope & 0x07;
+
+        if let Some(sweep) = self.sweep.as_mut() {
+            let pace = (sweep.nr10 >> 4) & 0x07;
+            let step = sweep.nr10 & 0x07;
+            sweep.shadow_frequency = self.frequency;
+            sweep.timer = if pace == 0 { 8 } else { pace };
+            sweep.enabled = pace != 0 || step != 0;
+            // The overflow check runs immediately when a step is set.
+            if step != 0 && self.next_sweep_frequency() > 0x7FF {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: TCycles) {
+        let mut cycles = cycles;
+        while cycles >= self.frequency_timer {
+            cycles -= self.frequency_timer;
+            self.frequency_timer = self.period();
+            self.duty_pos = (self.duty_pos + 1) & 0x07;
+        }
+        self.frequency_timer -= cycles;
+    }
+
+    fn clock_length(&mut self) {
+        if self.length_enable && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        let pace = self.envelope & 0x07;
+        if pace == 0 {
+            return;
+        }
+
+        self.envelope_timer = self.envelope_timer.saturating_sub(1);
+        if self.envelope_timer == 0 {
+            self.envelope_timer = pace;
+            let increase = self.envelope & 0x08 != 0;
+            if increase && self.volume < 0x0F {
+                self.volume += 1;
+            } else if !increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+
+        let pace = (sweep.nr10 >> 4) & 0x07;
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer != 0 {
+            return;
+        }
+        sweep.timer = if pace == 0 { 8 } else { pace };
+
+        if !sweep.enabled || pace == 0 {
+            return;
+        }
+
+        let step = sweep.nr10 & 0x07;
+        let next = self.next_sweep_frequency();
+        if next > 0x7FF {
+            self.enabled = false;
+        } else if step != 0 {
+            if let Some(sweep) = self.sweep.as_mut() {
+                sweep.shadow_frequency = next;
+            }
+            self.frequency = next;
+            // The new frequency is checked once more, without being applied.
+            if self.next_sweep_frequency() > 0x7FF {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn next_sweep_frequency(&self) -> u16 {
+        let Some(sweep) = self.sweep.as_ref() else {
+            return self.frequency;
+        };
+
+        let delta = sweep.shadow_frequency >> (sweep.nr10 & 0x07);
+        if sweep.nr10 & 0x08 != 0 {
+            sweep.shadow_frequency.saturating_sub(delta)
+        } else {
+            sweep.shadow_frequency + delta
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && DUTY_TABLE[self.duty as usize][self.duty_pos as usize] != 0 {
+            self.volume
+        } else {
+            0
+        }
+    }
+
+    // The DAC maps the digital 0-15 output to -1.0..1.0.
+    fn dac_output(&self) -> f32 {
+        if self.dac_enabled() {
+            self.output() as f32 / 7.5 - 1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+// Wave channel, plays back the 32 4-bit samples stored in wave RAM, high nibble first.
+//
+// NR30 [ 7 - - - - - - - ] 7: DAC enable
+// NR31 [ 7 6 5 4 3 2 1 0 ] Initial length timer [write-only]
+// NR32 [ - 6 5 - - - - - ] 6-5: Output level: 0 = Mute, 1 = 100%, 2 = 50%, 3 = 25%
+// NR33 [ 7 6 5 4 3 2 1 0 ] Period low [write-only]
+// NR34 [ 7 6 - - - 2 1 0 ] 7: Trigger 6: Length enable 2-0: Period high [write-only]
+struct Wave {
+    enabled: bool,
+    dac_enable: bool,
+    length_counter: u16,
+    length_enable: bool,
+    output_level: u8,
+    frequency: u16,
+    frequency_timer: TCycles,
+    position: u8, // Sample index 0-31
+    ram: [u8; 0x10],
+}
+
+impl Wave {
+    fn new() -> Self {
+        Wave {
+            enabled: false,
+            dac_enable: false,
+            length_counter: 0,
+            length_enable: false,
+            output_level: 0,
+            frequency: 0,
+            frequency_timer: 2048 * 2,
+            position: 0,
+            ram: [0; 0x10],
+        }
+    }
+
+    fn rb(&self, reg: u16) -> u8 {
+        match reg {
+            0 => (self.dac_enable as u8) << 7 | 0x7F,
+            1 => 0xFF,
+            2 => self.output_level << 5 | 0x9F,
+            3 => 0xFF,
+            4 => (self.length_enable as u8) << 6 | 0xBF,
+            _ => unreachable!(),
+        }
+    }
+
+    fn wb(&mut self, reg: u16, value: u8) {
+        match reg {
+            0 => {
+                self.dac_enable = value & 0x80 != 0;
+                if !self.dac_enable {
+                    self.enabled = false;
+                }
+            }
+            1 => self.write_length(value),
+            2 => self.output_level = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x07) << 8);
+                self.length_enable = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_length(&mut self, value: u8) {
+        self.length_counter = 256 - value as u16;
+    }
+
+    fn power_off(&mut self) {
+        *self = Wave {
+            length_counter: self.length_counter,
+            ram: self.ram,
+            ..Wave::new()
+        };
+    }
+
+    fn period(&self) -> TCycles {
+        (2048 - self.frequency as TCycles) * 2
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enable;
+        if self.length_counter == 0 {
+            self.length_counter = 256;
+        }
+        self.frequency_timer = self.period();
+        self.position = 0;
+    }
+
+    fn tick(&mut self, cycles: TCycles) {
+        let mut cycles = cycles;
+        while cycles >= self.frequency_timer {
+            cycles -= self.frequency_timer;
+            self.frequency_timer = self.period();
+            self.position = (self.position + 1) & 0x1F;
+        }
+        self.frequency_timer -= cycles;
+    }
+
+    fn clock_length(&mut self) {
+        if self.length_enable && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let byte = self.ram[(self.position >> 1) as usize];
+        let sample = if self.position & 0x01 == 0 {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        };
+
+        match self.output_level {
+            0 => 0,
+            1 => sample,
+            2 => sample >> 1,
+            3 => sample >> 2,
+            _ => unreachable!(),
+        }
+    }
+
+    fn dac_output(&self) -> f32 {
+        if self.dac_enable {
+            self.output() as f32 / 7.5 - 1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+// The audio sink is host side and not part of the state.
+impl Snapshot for APU {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ch1.save_state(w);
+        self.ch2.save_state(w);
+        self.ch3.save_state(w);
+        w.bool(self.power);
+        w.u8(self.nr50);
+        w.u8(self.nr51);
+        w.u8(self.frame_sequencer);
+        w.u32(self.frame_sequencer_acc);
+        w.u32(self.sample_acc);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ch1.load_state(r)?;
+        self.ch2.load_state(r)?;
+        self.ch3.load_state(r)?;
+        self.power = r.bool()?;
+        self.nr50 = r.u8()?;
+        self.nr51 = r.u8()?;
+        self.frame_sequencer = r.u8()? & 0x07;
+        self.frame_sequencer_acc = r.u32()?;
+        self.sample_acc = r.u32()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Pulse {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        if let Some(sweep) = &self.sweep {
+            w.u8(sweep.nr10);
+            w.u8(sweep.timer);
+            w.u16(sweep.shadow_frequency);
+            w.bool(sweep.enabled);
+        }
+        w.u8(self.duty);
+        w.u8(self.duty_pos);
+        w.u8(self.length_counter);
+        w.bool(self.length_enable);
+        w.u8(self.envelope);
+        w.u8(self.volume);
+        w.u8(self.envelope_timer);
+        w.u16(self.frequency);
+        w.u32(self.frequency_timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.bool()?;
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.nr10 = r.u8()? & 0x7F;
+            sweep.timer = r.u8()?;
+            sweep.shadow_frequency = r.u16()?;
+            sweep.enabled = r.bool()?;
+        }
+        self.duty = r.u8()? & 0x03;
+        self.duty_pos = r.u8()? & 0x07;
+        self.length_counter = r.u8()?;
+        self.length_enable = r.bool()?;
+        self.envelope = r.u8()?;
+        self.volume = r.u8()? & 0x0F;
+        self.envelope_timer = r.u8()?;
+        self.frequency = r.u16()? & 0x7FF;
+        self.frequency_timer = r.u32()?.max(1);
+        Ok(())
+    }
+}
+
+impl Snapshot for Wave {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.bool(self.dac_enable);
+        w.u16(self.length_counter);
+        w.bool(self.length_enable);
+        w.u8(self.output_level);
+        w.u16(self.frequency);
+        w.u32(self.frequency_timer);
+        w.u8(self.position);
+        w.bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.bool()?;
+        self.dac_enable = r.bool()?;
+        self.length_counter = r.u16()?;
+        self.length_enable = r.bool()?;
+        self.output_level = r.u8()? & 0x03;
+        self.frequency = r.u16()? & 0x7FF;
+        self.frequency_timer = r.u32()?.max(1);
+        self.position = r.u8()? & 0x1F;
+        r.bytes(&mut self.ram)
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    ffi::OsStr,
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use crate::cart::CartError;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZIP_MAGIC: [u8; 4] = *b"PK\x03\x04";
+
+// The largest cartridges hold 8 MiB, anything that unpacks past that isn't a ROM.
+const MAX_ROM_SIZE: u64 = 8 * 1024 * 1024;
+
+// Unpacks `data` if it's a .gz or .zip, going by the magic bytes rather than the extension.
+// Anything else is returned as is.
+pub fn unpack(data: Vec<u8>) -> Result<Vec<u8>, CartError> {
+    if data.starts_with(&GZIP_MAGIC) {
+        read_rom(GzDecoder::new(data.as_slice()))
+    } else if data.starts_with(&ZIP_MAGIC) {
+        unzip(data)
+    } else {
+        Ok(data)
+    }
+}
+
+// A lone file is taken whatever its name, otherwise the first .gb/.gbc entry.
+fn unzip(data: Vec<u8>) -> Result<Vec<u8>, CartError> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(io::Error::from)?;
+
+    let files: Vec<usize> = (0..archive.len())
+        .filter(|&i| archive.by_index(i).is_ok_and(|entry| entry.is_file()))
+        .collect();
+    let index = match files[..] {
+        [index] => index,
+        _ => *files
+            .iter()
+            .find(|&&i| archive.name_for_index(i).is_some_and(is_rom_name))
+            .ok_or(CartError::AmbiguousArchive)?,
+    };
+
+    let entry = archive.by_index(index).map_err(io::Error::from)?;
+    read_rom(entry)
+}
+
+// Stops reading past MAX_ROM_SIZE, the sizes archives declare can't be trusted.
+fn read_rom(reader: impl Read) -> Result<Vec<u8>, CartError> {
+    let mut rom = Vec::new();
+    reader.take(MAX_ROM_SIZE + 1).read_to_end(&mut rom)?;
+    if rom.len() as u64 > MAX_ROM_SIZE {
+        return Err(CartError::ArchiveTooLarge);
+    }
+    Ok(rom)
+}
+
+fn is_rom_name(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gb") || ext.eq_ignore_ascii_case("gbc"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::GzEncoder};
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+    use crate::cart::Cart;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn zip_picks_the_rom() {
+        let data = zip(&[("README.txt", b"readme"), ("game.GBC", b"rom")]);
+        assert_eq!(unpack(data).unwrap(), b"rom");
+    }
+
+    #[test]
+    fn zip_without_rom_is_ambiguous() {
+        let data = zip(&[("README.txt", b"readme"), ("notes.txt", b"notes")]);
+        assert!(matches!(unpack(data), Err(CartError::AmbiguousArchive)));
+    }
+
+    #[test]
+    fn oversized_archives_are_rejected() {
+        let bomb = vec![0; MAX_ROM_SIZE as usize + 1];
+        assert!(matches!(
+            unpack(gzip(&bomb)),
+            Err(CartError::ArchiveTooLarge)
+        ));
+        assert!(matches!(
+            unpack(zip(&[("game.gb", &bomb)])),
+            Err(CartError::ArchiveTooLarge)
+        ));
+
+        let rom = vec![0; MAX_ROM_SIZE as usize];
+        assert_eq!(unpack(gzip(&rom)).unwrap().len(), rom.len());
+    }
+
+    #[test]
+    fn gzipped_rom_saves_next_to_it() {
+        assert_eq!(Cart::save_path("roms/foo.gb.gz"), Path::new("roms/foo.sav"));
+        assert_eq!(Cart::save_path("roms/foo.gb"), Path::new("roms/foo.sav"));
+    }
+}
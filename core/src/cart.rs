@@ -1,8 +1,29 @@
-use crate::mbc::{
-    MemoryController, Missing, mbc1::Mbc1, mbc2::Mbc2, mbc3::Mbc3, rom_only::RomOnly,
+pub use crate::mbc::rtc::Clock;
+#[cfg(feature = "std")]
+pub use crate::mbc::rtc::SystemClock;
+
+#[cfg(feature = "std")]
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
 };
 
+use crate::{
+    mbc::{Controller, Mbc, mbc1::Mbc1, mbc2::Mbc2, mbc3::Mbc3, mbc5::Mbc5, rom_only::RomOnly},
+    state::{self, Snapshot, StateError, StateReader, StateWriter},
+};
+// Boxed controllers dispatch through the vtable and don't need the trait in scope.
+#[cfg(not(feature = "dyn-mbc"))]
+use crate::mbc::MemoryController;
+
 const HEADER_END: usize = 0x14F;
+const OFFSET_LOGO_START: usize = 0x104;
 const OFFSET_TITLE_START: usize = 0x134;
 const OFFSET_TITLE_END: usize = 0x143;
 const OFFSET_CGB_FLAG: usize = 0x143;
@@ -36,30 +57,46 @@ pub struct CartHeader {
     global_checksum: u16,
 }
 
-fn rom_size_from_id(id: u8) -> u32 {
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// MBC1M multicarts are 1 MiB and hold one game per 256 KiB, each with its own header.
+// The header can't tell them apart, so look for the Nintendo logo of the other games.
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    const GAME_SIZE: usize = 0x40000;
+
+    if rom.len() != 4 * GAME_SIZE {
+        return false;
+    }
+
+    let logos = (0..4)
+        .filter(|game| {
+            let start = game * GAME_SIZE + OFFSET_LOGO_START;
+            rom[start..start + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+        })
+        .count();
+    logos > 1
+}
+
+fn rom_size_from_id(id: u8) -> Option<u32> {
     match id {
-        0x00 => 32 * 1024,
-        0x01 => 64 * 1024,
-        0x02 => 128 * 1024,
-        0x03 => 256 * 1024,
-        0x04 => 512 * 1024,
-        0x05 => 1024 * 1024,
-        0x06 => 2 * 1024 * 1024,
-        0x07 => 4 * 1024 * 1024,
-        0x08 => 8 * 1024 * 1024,
-        _ => unreachable!(),
+        0x00..=0x08 => Some((32 * 1024) << id),
+        _ => None,
     }
 }
 
-fn ram_size_from_id(id: u8) -> u32 {
+fn ram_size_from_id(id: u8) -> Option<u32> {
     match id {
-        0x00 => 0,
-        0x01 => panic!("Should be unused"),
-        0x02 => 8 * 1024,
-        0x03 => 32 * 1024,
-        0x04 => 128 * 1024,
-        0x05 => 64 * 1024,
-        _ => unreachable!(),
+        0x00 => Some(0),
+        0x01 => Some(2 * 1024), // Unofficial, listed in some docs and used by a few homebrew
+        0x02 => Some(8 * 1024),
+        0x03 => Some(32 * 1024),
+        0x04 => Some(128 * 1024),
+        0x05 => Some(64 * 1024),
+        _ => None,
     }
 }
 
@@ -77,9 +114,21 @@ impl CartHeader {
 
         let cgb_flag = rom[OFFSET_CGB_FLAG];
         let sgb_flag = rom[OFFSET_SGB_FLAG];
-        let cartridge_type = rom[OFFSET_CARTRIDGE_TYPE];
-        let rom_size = rom_size_from_id(rom[OFFSET_ROM_SIZE]);
-        let ram_size = ram_size_from_id(rom[OFFSET_RAM_SIZE]);
+        let code = rom[OFFSET_CARTRIDGE_TYPE];
+        let cartridge_type =
+            CartridgeType::from_code(code).ok_or(CartError::UnsupportedCartridgeType { code })?;
+        let id = rom[OFFSET_ROM_SIZE];
+        let rom_size = rom_size_from_id(id).ok_or(CartError::InvalidRomSize { id })?;
+        let id = rom[OFFSET_RAM_SIZE];
+        // Homebrew linkers sometimes write odd values here, a single bank covers most of them.
+        let ram_size = match ram_size_from_id(id) {
+            Some(size) => size,
+            None => {
+                #[cfg(feature = "log")]
+                log::warn!("unknown ram size 0x{id:02X}, assuming 8 KiB");
+                8 * 1024
+            }
+        };
         let destination_code = rom[OFFSET_DESTINATION_CODE];
         let old_licensee_code = rom[OFFSET_LICENSEE_OLD];
         let mask_rom_version = rom[OFFSET_MASK_ROM_VERSION];
@@ -95,7 +144,7 @@ impl CartHeader {
             cgb_flag,
             new_licensee_code,
             sgb_flag,
-            cartridge_type: CartridgeType::from_code(cartridge_type),
+            cartridge_type,
             rom_size,
             ram_size,
             destination_code,
@@ -108,8 +157,47 @@ impl CartHeader {
     }
 }
 
-impl std::fmt::Display for CartHeader {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl CartHeader {
+    // The boot ROM refuses to start a cart whose header checksum doesn't match.
+    pub fn is_header_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    // Set for both CGB enhanced (0x80) and CGB only (0xC0) games.
+    pub fn is_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn cgb_flag(&self) -> u8 {
+        self.cgb_flag
+    }
+
+    pub fn cartridge_type(&self) -> &CartridgeType {
+        &self.cartridge_type
+    }
+
+    // ROM size in bytes, as declared by the header.
+    pub fn rom_size(&self) -> u32 {
+        self.rom_size
+    }
+
+    // Number of 16 KiB ROM banks.
+    pub fn rom_banks(&self) -> u32 {
+        self.rom_size / 0x4000
+    }
+
+    // External RAM size in bytes, 0 when the cart has none.
+    pub fn ram_size(&self) -> u32 {
+        self.ram_size
+    }
+}
+
+impl core::fmt::Display for CartHeader {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "Title: {}, CGB Flag: 0x{:02X}, Cartridge Type: {:?}, ROM Size: 0x{:02X}, RAM Size: 0x{:02X}",
@@ -120,62 +208,203 @@ impl std::fmt::Display for CartHeader {
 
 #[derive(Debug)]
 pub enum CartError {
-    RomTooSmall { len: usize },
+    RomTooSmall {
+        len: usize,
+    },
+    UnsupportedCartridgeType {
+        code: u8,
+    },
+    BadHeaderChecksum {
+        expected: u8,
+        found: u8,
+    },
+    InvalidRomSize {
+        id: u8,
+    },
+    InvalidRamSize {
+        size: usize,
+    },
+    RomSizeMismatch {
+        declared: usize,
+        actual: usize,
+    },
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    // A .zip with several files, none of them a .gb or .gbc
+    #[cfg(feature = "archive")]
+    AmbiguousArchive,
+    // Unpacks to more than the largest cartridge
+    #[cfg(feature = "archive")]
+    ArchiveTooLarge,
 }
 
-impl std::fmt::Display for CartError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for CartError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             CartError::RomTooSmall { len } => {
                 write!(f, "rom is too small for header: {len} bytes")
             }
+            CartError::UnsupportedCartridgeType { code } => {
+                write!(f, "unsupported cartridge type: 0x{code:02X}")
+            }
+            CartError::InvalidRomSize { id } => write!(f, "invalid rom size: 0x{id:02X}"),
+            CartError::InvalidRamSize { size } => write!(f, "invalid ram size: {size} bytes"),
+            CartError::RomSizeMismatch { declared, actual } => {
+                write!(
+                    f,
+                    "rom is smaller than its header declares: {actual} bytes, expected {declared}"
+                )
+            }
+            CartError::BadHeaderChecksum { expected, found } => {
+                write!(
+                    f,
+                    "bad header checksum: expected 0x{expected:02X}, found 0x{found:02X}"
+                )
+            }
+            #[cfg(feature = "std")]
+            CartError::Io(err) => write!(f, "failed to read rom: {err}"),
+            #[cfg(feature = "archive")]
+            CartError::AmbiguousArchive => {
+                write!(
+                    f,
+                    "archive holds several files and none of them is a .gb/.gbc rom"
+                )
+            }
+            #[cfg(feature = "archive")]
+            CartError::ArchiveTooLarge => write!(f, "archive unpacks to more than 8 MiB"),
         }
     }
 }
 
-impl std::error::Error for CartError {}
+impl core::error::Error for CartError {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for CartError {
+    fn from(err: io::Error) -> Self {
+        CartError::Io(err)
+    }
+}
 
 pub struct Cart {
     pub header: CartHeader,
-    pub controller: Box<dyn MemoryController>,
+    controller: Controller,
+    rom_checksum: u64,
 }
 
 impl Cart {
+    #[cfg(feature = "std")]
     pub fn from_bytes(rom: Vec<u8>, save_data: Option<Vec<u8>>) -> Result<Cart, CartError> {
-        let header = CartHeader::parse(&rom)?;
+        Cart::from_bytes_with_clock(rom, save_data, Box::new(SystemClock), None)
+    }
+
+    // Reads the ROM at `path`, along with the battery save next to it if there is one.
+    // With the `archive` feature the ROM may also be packed in a .gz or a .zip.
+    #[cfg(feature = "std")]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Cart, CartError> {
+        let path = path.as_ref();
+        let rom = std::fs::read(path)?;
+        #[cfg(feature = "archive")]
+        let rom = crate::archive::unpack(rom)?;
+        let save_data = std::fs::read(Cart::save_path(path)).ok();
+        Cart::from_bytes(rom, save_data)
+    }
 
-        let controller: Box<dyn MemoryController> = match header.cartridge_type {
-            CartridgeType::RomOnly => Box::new(RomOnly::new(rom, header.ram_size)),
+    // Where the battery save of the ROM at `rom_path` lives: same name, `.sav` extension.
+    // A `.gb.gz` saves next to it as `.sav` too, not `.gb.sav`.
+    #[cfg(feature = "std")]
+    pub fn save_path<P: AsRef<Path>>(rom_path: P) -> PathBuf {
+        let rom_path = rom_path.as_ref();
+        if rom_path.extension().is_some_and(|ext| ext == "gz") {
+            rom_path.with_extension("").with_extension("sav")
+        } else {
+            rom_path.with_extension("sav")
+        }
+    }
+
+    // Same as `from_bytes`, with the time source of the cartridge RTC (MBC3 only).
+    // `ram_size` overrides the RAM size declared by the header, for homebrew with a wrong one.
+    // It must be a size a header can declare.
+    pub fn from_bytes_with_clock(
+        rom: Vec<u8>,
+        save_data: Option<Vec<u8>>,
+        clock: Box<dyn Clock>,
+        ram_size: Option<usize>,
+    ) -> Result<Cart, CartError> {
+        let mut header = CartHeader::parse(&rom)?;
+        if let Some(size) = ram_size {
+            header.ram_size = (0x00..=0x05)
+                .filter_map(ram_size_from_id)
+                .find(|&declared| declared as usize == size)
+                .ok_or(CartError::InvalidRamSize { size })?;
+        }
+        // Bad dumps are often truncated, the MBCs would index past the end.
+        if rom.len() < header.rom_size as usize {
+            return Err(CartError::RomSizeMismatch {
+                declared: header.rom_size as usize,
+                actual: rom.len(),
+            });
+        }
+        let rom_checksum = state::checksum(&rom);
+
+        let mbc = match header.cartridge_type {
+            CartridgeType::RomOnly => Mbc::RomOnly(RomOnly::new(rom, header.ram_size)),
             CartridgeType::Mbc1 {
                 has_ram,
                 has_battery,
-            } => Box::new(Mbc1::new(
+            } => {
+                let multicart = is_mbc1_multicart(&rom);
+                Mbc::Mbc1(Mbc1::new(
+                    rom,
+                    header.ram_size,
+                    has_ram,
+                    has_battery,
+                    multicart,
+                    save_data,
+                ))
+            }
+            CartridgeType::Mbc2 { has_battery } => {
+                Mbc::Mbc2(Mbc2::new(rom, has_battery, save_data))
+            }
+            CartridgeType::Mbc3 {
+                has_timer,
+                has_ram,
+                has_battery,
+            } => Mbc::Mbc3(Mbc3::new(
                 rom,
                 header.ram_size,
                 has_ram,
                 has_battery,
+                has_timer,
                 save_data,
+                clock,
             )),
-            CartridgeType::Mbc2 { has_battery } => Box::new(Mbc2::new(rom, has_battery, save_data)),
-            CartridgeType::Mbc3 {
-                has_timer,
+            CartridgeType::Mbc5 {
                 has_ram,
                 has_battery,
-            } => Box::new(Mbc3::new(
+                has_rumble,
+            } => Mbc::Mbc5(Mbc5::new(
                 rom,
                 header.ram_size,
                 has_ram,
                 has_battery,
-                has_timer,
+                has_rumble,
                 save_data,
             )),
-            _ => Box::new(Missing),
+            _ => {
+                return Err(CartError::UnsupportedCartridgeType {
+                    code: rom[OFFSET_CARTRIDGE_TYPE],
+                });
+            }
         };
 
-        Ok(Cart { header, controller })
+        Ok(Cart {
+            header,
+            controller: mbc.into_controller(),
+            rom_checksum,
+        })
     }
 
-    pub fn rb(&mut self, addr: u16) -> u8 {
+    pub fn rb(&self, addr: u16) -> u8 {
         self.controller.rb(addr)
     }
 
@@ -187,9 +416,44 @@ impl Cart {
         self.controller.save()
     }
 
+    pub fn take_dirty(&mut self) -> bool {
+        self.controller.take_dirty()
+    }
+
+    pub fn reset(&mut self) {
+        self.controller.reset()
+    }
+
     pub fn get_title(&self) -> String {
         self.header.title.clone()
     }
+
+    // Fails the same way the boot ROM would on a corrupted header.
+    pub fn verify_header(&self) -> Result<(), CartError> {
+        if self.header.is_header_valid() {
+            Ok(())
+        } else {
+            Err(CartError::BadHeaderChecksum {
+                expected: self.header.computed_header_checksum,
+                found: self.header.header_checksum,
+            })
+        }
+    }
+
+    pub fn rom_checksum(&self) -> u64 {
+        self.rom_checksum
+    }
+}
+
+// The ROM itself is never part of a save state, only the MBC registers and RAM.
+impl Snapshot for Cart {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.controller.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.controller.load_state(r)
+    }
 }
 
 fn compute_header_checksum(rom: &[u8]) -> u8 {
@@ -204,7 +468,7 @@ fn ascii_from_bytes(bytes: &[u8]) -> String {
     String::from_utf8_lossy(&bytes[..term]).to_string()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CartridgeType {
     RomOnly,
     Mbc1 {
@@ -248,8 +512,8 @@ pub enum CartridgeType {
 }
 
 impl CartridgeType {
-    pub fn from_code(code: u8) -> Self {
-        match code {
+    pub fn from_code(code: u8) -> Option<Self> {
+        let cartridge_type = match code {
             0x00 => CartridgeType::RomOnly,
             0x01 => CartridgeType::Mbc1 {
                 has_ram: false,
@@ -348,7 +612,8 @@ impl CartridgeType {
                 has_ram: true,
                 has_battery: true,
             },
-            _ => unreachable!(),
-        }
+            _ => return None,
+        };
+        Some(cartridge_type)
     }
 }
@@ -1,12 +1,14 @@
 use crate::{
-    cpu::{CPU, Cycles},
+    cpu::{CPU, CpuError, Cycles},
     mmu::MMU,
 };
 
 pub fn op_xxx(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
     let pc = cpu.r.pc.wrapping_sub(1);
-    let op = mmu.rb(pc);
-    panic!("Illegal opcode: 0x{op:02X} at PC=0x{pc:04X}")
+    let opcode = mmu.rb(pc);
+    // Illegal opcodes hang the CPU until it is powered off.
+    cpu.fault = Some(CpuError::IllegalOpcode { opcode, pc });
+    1
 }
 
 // ALU
@@ -197,12 +199,19 @@ pub fn op_nop(_: &mut CPU, _: &mut MMU) -> Cycles {
 pub fn op_stop(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
     // STOP is a 2 byte instruction
     cpu.rb(mmu);
-    cpu.stop();
+    // On CGB, STOP with a speed switch armed changes speed and carries on.
+    if mmu.speed_switch_armed() {
+        mmu.switch_speed();
+    } else {
+        cpu.stop();
+    }
+    // Entering STOP resets the divider.
+    mmu.reset_div();
     1
 }
 
-pub fn op_halt(cpu: &mut CPU, _: &mut MMU) -> Cycles {
-    cpu.halt();
+pub fn op_halt(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    cpu.halt(mmu);
     1
 }
 
@@ -257,42 +266,50 @@ pub fn op_add_sp_r8(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
 }
 
 // 16-bit inc/dec
-pub fn op_inc_bc(cpu: &mut CPU, _: &mut MMU) -> Cycles {
+pub fn op_inc_bc(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    mmu.oam_bug_inc_dec(cpu.r.bc());
     cpu.r.set_bc(cpu.r.bc().wrapping_add(1));
     2
 }
 
-pub fn op_inc_de(cpu: &mut CPU, _: &mut MMU) -> Cycles {
+pub fn op_inc_de(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    mmu.oam_bug_inc_dec(cpu.r.de());
     cpu.r.set_de(cpu.r.de().wrapping_add(1));
     2
 }
 
-pub fn op_inc_hl(cpu: &mut CPU, _: &mut MMU) -> Cycles {
+pub fn op_inc_hl(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    mmu.oam_bug_inc_dec(cpu.r.hl());
     cpu.r.set_hl(cpu.r.hl().wrapping_add(1));
     2
 }
 
-pub fn op_inc_sp(cpu: &mut CPU, _: &mut MMU) -> Cycles {
+pub fn op_inc_sp(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    mmu.oam_bug_inc_dec(cpu.r.sp);
     cpu.r.sp = cpu.r.sp.wrapping_add(1);
     2
 }
 
-pub fn op_dec_bc(cpu: &mut CPU, _: &mut MMU) -> Cycles {
+pub fn op_dec_bc(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    mmu.oam_bug_inc_dec(cpu.r.bc());
     cpu.r.set_bc(cpu.r.bc().wrapping_sub(1));
     2
 }
 
-pub fn op_dec_de(cpu: &mut CPU, _: &mut MMU) -> Cycles {
+pub fn op_dec_de(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    mmu.oam_bug_inc_dec(cpu.r.de());
     cpu.r.set_de(cpu.r.de().wrapping_sub(1));
     2
 }
 
-pub fn op_dec_hl(cpu: &mut CPU, _: &mut MMU) -> Cycles {
+pub fn op_dec_hl(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    mmu.oam_bug_inc_dec(cpu.r.hl());
     cpu.r.set_hl(cpu.r.hl().wrapping_sub(1));
     2
 }
 
-pub fn op_dec_sp(cpu: &mut CPU, _: &mut MMU) -> Cycles {
+pub fn op_dec_sp(cpu: &mut CPU, mmu: &mut MMU) -> Cycles {
+    mmu.oam_bug_inc_dec(cpu.r.sp);
     cpu.r.sp = cpu.r.sp.wrapping_sub(1);
     2
 }
@@ -910,9 +927,8 @@ pub fn op_dec_a(cpu: &mut CPU, _: &mut MMU) -> Cycles {
     1
 }
 
-#[allow(clippy::self_assignment, dead_code)]
 pub fn op_ld_b_b(cpu: &mut CPU, _: &mut MMU) -> Cycles {
-    cpu.r.b = cpu.r.b;
+    cpu.ld_b_b = true; // Breakpoint marker, see `CPU::take_ld_b_b`
     1
 }
 
@@ -1,34 +1,67 @@
+mod disasm;
 mod instructions;
 mod registers;
-
-use core::panic;
+mod trace;
 
 use crate::cpu::instructions::*;
 use crate::interrupt::{INTERRUPT_CYCLES, highest_priority};
-use crate::{cpu::registers::Registers, mmu::MMU};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::{cpu::registers::Registers, gameboy::Model, mmu::MMU};
+
+pub use disasm::Instruction;
+pub use trace::{TraceEntry, TraceHook};
 
 pub struct CPU {
     pub r: Registers,
     pub ime: bool, // IME: Interrupt master enable flag
     ime_delay: u8,
     halted: bool,
+    halt_bug: bool, // Next opcode fetch doesn't increment PC
     stopped: bool,
+    fault: Option<CpuError>, // Set once the CPU locked up
+    ld_b_b: bool,            // LD B,B executed since the last take_ld_b_b
+    trace_hook: Option<TraceHook>,
 }
 
 // return value is MACHINE cycles.
 type OP = fn(&mut CPU, &mut MMU) -> Cycles;
 pub type Cycles = u8;
 
+// Copy of the register file and execution flags, for debuggers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+    pub halted: bool,
+    pub stopped: bool,
+}
+
 impl CPU {
-    pub fn init() -> Self {
+    pub fn init(model: Model) -> Self {
         let mut r = Registers::default();
 
-        // This is the register state after the DMG Bios has run.
+        // This is the register state after the Bios of each model has run,
+        // games check A to tell them apart (0x01 DMG/SGB, 0xFF MGB, 0x11 CGB).
         // ref: [https://gbdev.io/pandocs/Power_Up_Sequence.html]
-        r.set_af(0x01B0);
-        r.set_bc(0x0013);
-        r.set_de(0x00D8);
-        r.set_hl(0x014D);
+        let (af, bc, de, hl) = match model {
+            Model::DMG => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            Model::MGB => (0xFFB0, 0x0013, 0x00D8, 0x014D),
+            Model::SGB => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::CGB => (0x1180, 0x0000, 0xFF56, 0x000D),
+        };
+        r.set_af(af);
+        r.set_bc(bc);
+        r.set_de(de);
+        r.set_hl(hl);
         r.sp = 0xFFFE;
         r.pc = 0x0100; // Program entrypoint
 
@@ -37,31 +70,59 @@ impl CPU {
             ime: false,
             ime_delay: 0,
             halted: false,
+            halt_bug: false,
             stopped: false,
+            fault: None,
+            ld_b_b: false,
+            trace_hook: None,
         }
     }
 
-    pub fn step(&mut self, mmu: &mut MMU) -> Cycles {
+    // LD B,B does nothing, so test ROMs and debuggers use it as a breakpoint marker.
+    pub fn take_ld_b_b(&mut self) -> bool {
+        core::mem::take(&mut self.ld_b_b)
+    }
+
+    // Register state at power on, when a boot ROM runs first.
+    pub fn power_on() -> Self {
+        let mut cpu = CPU::init(Model::DMG);
+        cpu.r = Registers::default();
+        cpu
+    }
+
+    pub fn step(&mut self, mmu: &mut MMU) -> Result<Cycles, CpuError> {
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+
         if self.halted {
             if mmu.pending_interrupts() != 0 {
                 self.halted = false;
             } else {
-                return 1;
+                return Ok(1);
             }
         }
 
-        // stubbed
+        // STOP mode is left once a button is pressed.
         if self.stopped {
-            return 1;
+            if mmu.joypad_pressed() {
+                self.stopped = false;
+            } else {
+                return Ok(1);
+            }
         }
 
         if self.ime
             && let Some(cycles) = self.service_interrupts(mmu)
         {
-            return cycles;
+            return Ok(cycles);
         }
 
-        let op = self.rb(mmu);
+        if self.trace_hook.is_some() {
+            self.trace(mmu);
+        }
+
+        let op = self.fetch_op(mmu);
 
         let cycles = if op == 0xCB {
             let cb = self.rb(mmu);
@@ -70,6 +131,10 @@ impl CPU {
             (OP_TABLE[op as usize])(self, mmu)
         };
 
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+
         // The effect of ei is delayed by one instruction.
         // This means that ei followed immediately by di does not allow any interrupts between them.
         if self.ime_delay > 0 {
@@ -79,7 +144,59 @@ impl CPU {
             }
         }
 
-        cycles
+        Ok(cycles)
+    }
+
+    // Called right before every instruction.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    fn trace(&mut self, mmu: &MMU) {
+        let entry = self.trace_entry(mmu);
+        if let Some(hook) = self.trace_hook.as_mut() {
+            hook(&entry);
+        }
+    }
+
+    pub fn trace_entry(&self, mmu: &MMU) -> TraceEntry {
+        let pc = self.r.pc;
+        TraceEntry {
+            pc,
+            bytes: [0, 1, 2, 3].map(|i| mmu.peek(pc.wrapping_add(i))),
+            a: self.r.a,
+            f: self.r.f(),
+            b: self.r.b,
+            c: self.r.c,
+            d: self.r.d,
+            e: self.r.e,
+            h: self.r.h,
+            l: self.r.l,
+            sp: self.r.sp,
+            cycles: mmu.cycles(),
+        }
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            a: self.r.a,
+            f: self.r.f(),
+            b: self.r.b,
+            c: self.r.c,
+            d: self.r.d,
+            e: self.r.e,
+            h: self.r.h,
+            l: self.r.l,
+            sp: self.r.sp,
+            pc: self.r.pc,
+            ime: self.ime,
+            halted: self.halted,
+            stopped: self.stopped,
+        }
     }
 
     fn service_interrupts(&mut self, mmu: &mut MMU) -> Option<Cycles> {
@@ -87,7 +204,7 @@ impl CPU {
         let interrupt = highest_priority(pending)?;
 
         self.ime = false;
-        mmu.clear_interrupt(interrupt.bit());
+        mmu.clear_interrupt(interrupt);
         call(self, mmu, interrupt.vector());
 
         Some(INTERRUPT_CYCLES)
@@ -105,6 +222,16 @@ impl CPU {
         self.r.sp = self.r.sp.wrapping_sub(val)
     }
 
+    fn fetch_op(&mut self, mmu: &mut MMU) -> u8 {
+        if self.halt_bug {
+            // The byte after HALT gets read twice.
+            self.halt_bug = false;
+            mmu.rb(self.r.pc)
+        } else {
+            self.rb(mmu)
+        }
+    }
+
     pub fn rb(&mut self, mmu: &mut MMU) -> u8 {
         let v = mmu.rb(self.r.pc);
         self.pc_inc(1);
@@ -143,11 +270,70 @@ impl CPU {
         self.stopped = true;
     }
 
-    pub fn halt(&mut self) {
-        self.halted = true;
+    pub fn halt(&mut self, mmu: &MMU) {
+        // HALT bug: with IME clear and an interrupt already pending the CPU doesn't halt,
+        // and fails to increment PC on the following opcode fetch.
+        if !self.ime && mmu.pending_interrupts() != 0 {
+            self.halt_bug = true;
+        } else {
+            self.halted = true;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    IllegalOpcode { opcode: u8, pc: u16 },
+}
+
+impl core::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CpuError::IllegalOpcode { opcode, pc } => {
+                write!(f, "illegal opcode 0x{opcode:02X} at PC=0x{pc:04X}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for CpuError {}
+
+impl Snapshot for CPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.r.save_state(w);
+        w.bool(self.ime);
+        w.u8(self.ime_delay);
+        w.bool(self.halted);
+        w.bool(self.halt_bug);
+        w.bool(self.stopped);
+        w.bool(self.fault.is_some());
+        if let Some(CpuError::IllegalOpcode { opcode, pc }) = self.fault {
+            w.u8(opcode);
+            w.u16(pc);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.r.load_state(r)?;
+        self.ime = r.bool()?;
+        self.ime_delay = r.u8()?;
+        self.halted = r.bool()?;
+        self.halt_bug = r.bool()?;
+        self.stopped = r.bool()?;
+        self.fault = if r.bool()? {
+            let opcode = r.u8()?;
+            let pc = r.u16()?;
+            Some(CpuError::IllegalOpcode { opcode, pc })
+        } else {
+            None
+        };
+        Ok(())
     }
 }
 
+// A generated `match op` dispatching to the same functions benched within noise of these
+// tables (cargo bench --bench cpu): every memory access ticks the whole machine, which
+// dwarfs the indirect call. The tables stay as the readable opcode map.
 pub const OP_TABLE: [OP; 256] = {
     let mut t: [OP; 256] = [op_xxx; 256];
 
@@ -1,3 +1,5 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
 #[derive(Default)]
 pub struct Registers {
     pub a: u8, // Accumulator
@@ -43,6 +45,11 @@ impl Registers {
         self.f = (value as u8) & 0xF0;
     }
 
+    #[inline]
+    pub fn f(&self) -> u8 {
+        self.f
+    }
+
     #[inline]
     pub fn bc(&self) -> u16 {
         build_w(self.b, self.c)
@@ -109,3 +116,29 @@ impl Registers {
         self.f = if v { self.f | C_BIT } else { self.f & !C_BIT };
     }
 }
+
+impl Snapshot for Registers {
+    fn save_state(&self, w: &mut StateWriter) {
+        for reg in [
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+        ] {
+            w.u8(reg);
+        }
+        w.u16(self.sp);
+        w.u16(self.pc);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.a = r.u8()?;
+        self.f = r.u8()? & 0xF0;
+        self.b = r.u8()?;
+        self.c = r.u8()?;
+        self.d = r.u8()?;
+        self.e = r.u8()?;
+        self.h = r.u8()?;
+        self.l = r.u8()?;
+        self.sp = r.u16()?;
+        self.pc = r.u16()?;
+        Ok(())
+    }
+}
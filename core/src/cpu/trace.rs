@@ -0,0 +1,33 @@
+use alloc::boxed::Box;
+
+// Machine state right before an instruction executes.
+#[derive(Clone, Copy, Debug)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub bytes: [u8; 4], // Opcode and the bytes following it, not all of them belong to the instruction
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub cycles: u64, // T-cycles since power on
+}
+
+pub type TraceHook = Box<dyn FnMut(&TraceEntry)>;
+
+// Gameboy Doctor log format
+// ref: [https://github.com/robert/gameboy-doctor]
+impl core::fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let [m0, m1, m2, m3] = self.bytes;
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{m0:02X},{m1:02X},{m2:02X},{m3:02X}",
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc
+        )
+    }
+}
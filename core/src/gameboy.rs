@@ -1,13 +1,91 @@
+pub use crate::apu::AudioSink;
+pub use crate::ppu::PpuRegs;
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+#[cfg(feature = "std")]
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
 use crate::{
-    cart::Cart,
-    cpu::CPU,
+    cart::{Cart, CartHeader},
+    cpu::{CPU, CpuError, CpuState, Cycles, Instruction, TraceHook},
     mmu::MMU,
     ppu::{SCREEN_H, SCREEN_W},
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+#[cfg(feature = "std")]
+use crate::{
+    cart::{Clock, SystemClock},
+    wav::{WAV_SAMPLE_RATE, WavWriter},
 };
 
 pub struct GameBoy {
     cpu: CPU,
     mmu: MMU,
+    breakpoints: BTreeSet<u16>,
+    resume_from: Option<u16>, // Breakpoint we last stopped at, skipped until PC moves on
+    ld_b_b_breakpoint: bool,
+    vblank_hook: Option<VBlankHook>,
+    hblank_hook: Option<HBlankHook>,
+    scanline_hook: Option<ScanlineHook>,
+    recording: Option<Vec<u8>>,          // One Buttons byte per frame
+    playback: Option<vec::IntoIter<u8>>, // Overrides the keys until it runs out
+    mid_frame: bool, // Stopped at a breakpoint, the current frame already has its input
+    model: Model,
+}
+
+// The console being emulated, it decides the post boot state and whether CGB features exist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    DMG, // Original Game Boy
+    MGB, // Game Boy Pocket
+    SGB, // Super Game Boy, without the border and palette commands
+    CGB, // Game Boy Color
+}
+
+impl Model {
+    // Picks the CGB for carts with CGB support and the DMG for the rest.
+    pub fn detect(header: &CartHeader) -> Self {
+        if header.is_cgb() {
+            Model::CGB
+        } else {
+            Model::DMG
+        }
+    }
+}
+
+pub type VBlankHook = Box<dyn FnMut()>;
+pub type HBlankHook = Box<dyn FnMut(u8)>; // Called with LY
+pub type ScanlineHook = Box<dyn FnMut(u8, &PpuRegs)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepResult {
+    pub cycles: Cycles,
+    pub frame_ready: bool,
+    pub magic_breakpoint: bool, // LD B,B executed with `set_ld_b_b_breakpoint` on
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunOutcome {
+    FrameReady,
+    CycleLimit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    FrameReady,
+    Breakpoint(u16),
+    MagicBreakpoint, // LD B,B executed, see `set_ld_b_b_breakpoint`
 }
 
 // Using a green tint to emulate the DMG-01 LCD screen.
@@ -20,48 +98,480 @@ const LCD_PALETTE: [u32; 4] = [
 
 impl GameBoy {
     pub fn new(cart: Cart) -> Self {
-        let title = cart.get_title();
-        println!("Booted ROM: {title}");
+        let model = Model::detect(&cart.header);
+        GameBoy::new_with_model(cart, model)
+    }
+
+    pub fn new_with_model(cart: Cart, model: Model) -> Self {
+        #[cfg(feature = "log")]
+        {
+            log::info!("Booted ROM: {}", cart.get_title());
+            log::info!("{}", cart.header);
+
+            if let Err(err) = cart.verify_header() {
+                log::warn!("{err}, the real hardware would not boot this ROM");
+            }
+        }
 
-        let header = &cart.header;
+        // A DMG only cart on a CGB runs without the CGB features.
+        let cgb = model == Model::CGB && cart.header.is_cgb();
 
-        println!("{header}");
+        let mut mmu = MMU::new(cart, cgb);
+        mmu.set_oam_bug(model != Model::CGB);
 
         GameBoy {
-            cpu: CPU::init(),
-            mmu: MMU::new(cart),
+            cpu: CPU::init(model),
+            mmu,
+            breakpoints: BTreeSet::new(),
+            resume_from: None,
+            ld_b_b_breakpoint: false,
+            vblank_hook: None,
+            hblank_hook: None,
+            scanline_hook: None,
+            recording: None,
+            playback: None,
+            mid_frame: false,
+            model,
+        }
+    }
+
+    // Boots through a real DMG or CGB boot ROM instead of starting at 0x0100 with the post boot state.
+    pub fn with_boot_rom(cart: Cart, boot: Vec<u8>) -> Self {
+        let mut gameboy = GameBoy::new(cart);
+        gameboy.mmu.map_boot_rom(boot);
+        gameboy.cpu = CPU::power_on();
+        gameboy
+    }
+
+    // Restarts the loaded ROM as if the console was switched off and on again,
+    // battery backed RAM survives.
+    pub fn reset(&mut self) {
+        self.cpu = if self.mmu.has_boot_rom() {
+            CPU::power_on()
+        } else {
+            CPU::init(self.model)
+        };
+        self.mmu.reset();
+        self.resume_from = None;
+        self.mid_frame = false;
+    }
+
+    // Records the input of every following frame, for replaying it later with `play_input`.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    pub fn stop_recording(&mut self) -> Vec<u8> {
+        self.recording.take().unwrap_or_default()
+    }
+
+    // Feeds back recorded input one frame at a time, ignoring the keys given to `run_frame`.
+    // Starting from the same state this reproduces the recorded run exactly.
+    pub fn play_input(&mut self, input: Vec<u8>) {
+        self.playback = Some(input.into_iter());
+    }
+
+    fn frame_input(&mut self, key_states: KeyStates) -> KeyStates {
+        let mut keys = key_states;
+        if let Some(playback) = self.playback.as_mut() {
+            match playback.next() {
+                Some(bits) => keys = KeyStates::from_bits(Buttons(bits)),
+                None => self.playback = None,
+            }
+        }
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(keys.to_bits().bits());
+        }
+
+        keys
+    }
+
+    // Execution stops right before the instruction at `addr`.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    // Stops `run_frame` right after an LD B,B, which mooneye and other test ROMs use to
+    // signal they're done. Off by default, where it stays a no-op.
+    pub fn set_ld_b_b_breakpoint(&mut self, enabled: bool) {
+        self.ld_b_b_breakpoint = enabled;
+    }
+
+    fn hit_breakpoint(&mut self) -> bool {
+        let pc = self.cpu.r.pc;
+        if self.resume_from == Some(pc) {
+            return false;
+        }
+        self.resume_from = None;
+
+        if self.breakpoints.contains(&pc) {
+            self.resume_from = Some(pc);
+            return true;
+        }
+        false
+    }
+
+    // Runs a single instruction, or services a single interrupt.
+    pub fn step_instruction(&mut self) -> Result<Cycles, CpuError> {
+        Ok(self.step_cpu()?.cycles)
+    }
+
+    // Same as `step_instruction` with `key_states` held, for hosts driving the emulation loop
+    // themselves. Address breakpoints and input recording are left to `run_frame`, an LD B,B
+    // breakpoint only shows up in the result.
+    pub fn step(&mut self, key_states: impl Into<KeyStates>) -> Result<StepResult, CpuError> {
+        self.mmu.handle_joypad(key_states.into());
+        self.step_cpu()
+    }
+
+    fn step_cpu(&mut self) -> Result<StepResult, CpuError> {
+        let cycles = self.cpu.step(&mut self.mmu)?;
+        let magic_breakpoint = self.cpu.take_ld_b_b() && self.ld_b_b_breakpoint;
+        let frame_ready = self.tick(cycles);
+        Ok(StepResult {
+            cycles,
+            frame_ready,
+            magic_breakpoint,
+        })
+    }
+
+    fn tick(&mut self, cycles: Cycles) -> bool {
+        let frame_ready = self.mmu.finish_step(cycles);
+
+        let events = self.mmu.ppu_events();
+        if let Some(regs) = events.hblank {
+            if let Some(hook) = self.hblank_hook.as_mut() {
+                hook(regs.ly);
+            }
+            if let Some(hook) = self.scanline_hook.as_mut() {
+                hook(regs.ly, &regs);
+            }
+        }
+        if events.vblank
+            && let Some(hook) = self.vblank_hook.as_mut()
+        {
+            hook();
         }
+
+        frame_ready
+    }
+
+    // Called when the PPU enters VBlank, from inside the emulation loop.
+    pub fn on_vblank(&mut self, hook: VBlankHook) {
+        self.vblank_hook = Some(hook);
+    }
+
+    pub fn clear_vblank_hook(&mut self) {
+        self.vblank_hook = None;
+    }
+
+    // Called when the PPU enters HBlank on a visible line, right after that line was drawn.
+    pub fn on_hblank(&mut self, hook: HBlankHook) {
+        self.hblank_hook = Some(hook);
+    }
+
+    pub fn clear_hblank_hook(&mut self) {
+        self.hblank_hook = None;
+    }
+
+    // Called for every visible line with the LY, scroll, window and palette registers
+    // it was drawn with, to check mid-frame raster effects.
+    pub fn on_scanline(&mut self, hook: ScanlineHook) {
+        self.scanline_hook = Some(hook);
+    }
+
+    pub fn clear_scanline_hook(&mut self) {
+        self.scanline_hook = None;
+    }
+
+    // Calls `hook` right before every instruction, e.g. to diff against reference logs.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.cpu.set_trace_hook(hook);
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.cpu.clear_trace_hook();
+    }
+
+    // Current CPU state as a Gameboy Doctor log line, a TraceEntry formats the same way.
+    pub fn doctor_log_line(&self) -> String {
+        self.cpu.trace_entry(&self.mmu).to_string()
+    }
+
+    // Disassembly of the instruction about to run, e.g. "LD A, ($FF44)".
+    pub fn current_instruction(&self) -> String {
+        self.instruction_at(self.cpu.r.pc).to_string()
+    }
+
+    pub fn instruction_at(&self, pc: u16) -> Instruction {
+        let bytes = [0, 1, 2].map(|i| self.mmu.peek(pc.wrapping_add(i)));
+        Instruction::new(pc, bytes)
+    }
+
+    // Mixed stereo output, resampled to `sample_rate` (e.g. 44100 or 48000).
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>, sample_rate: u32) {
+        self.mmu.set_audio_sink(sink, sample_rate);
+    }
+
+    pub fn clear_audio_sink(&mut self) {
+        self.mmu.clear_audio_sink();
     }
 
-    pub fn run_frame(&mut self, key_states: KeyStates) {
-        self.mmu.handle_joypad(key_states);
+    // Records the mixed output to a 44.1 kHz WAV file, next to any live audio sink.
+    // A directory `path` gets a timestamped file inside it, the path written to is returned.
+    #[cfg(feature = "std")]
+    pub fn start_wav_capture<P: AsRef<Path>>(&mut self, path: P) -> io::Result<PathBuf> {
+        let mut path = path.as_ref().to_path_buf();
+        if path.is_dir() {
+            path.push(format!("capture-{}.wav", SystemClock.now_secs()));
+        }
+
+        let writer = WavWriter::create(&path)?;
+        self.mmu
+            .set_audio_capture(Box::new(writer), WAV_SAMPLE_RATE);
+        Ok(path)
+    }
+
+    // Flushes the remaining samples and closes the WAV file.
+    #[cfg(feature = "std")]
+    pub fn stop_wav_capture(&mut self) {
+        self.mmu.clear_audio_capture();
+    }
+
+    // Solo or mute channels 1-4 in the mixed output, without touching the emulated registers.
+    pub fn set_channel_enabled(&mut self, channel: u8, enabled: bool) {
+        self.mmu.set_channel_enabled(channel, enabled);
+    }
+
+    pub fn cart_header(&self) -> &CartHeader {
+        self.mmu.cart_header()
+    }
+
+    // T-cycles run since power on, in CPU cycles so double speed counts twice as fast.
+    pub fn total_cycles(&self) -> u64 {
+        self.mmu.cycles()
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    // True when the Game Boy Color features are enabled.
+    pub fn is_cgb(&self) -> bool {
+        self.mmu.is_cgb()
+    }
+
+    // CGB double speed mode, the CPU, timer and serial run twice as fast
+    // while a frame still takes the same time.
+    pub fn is_double_speed(&self) -> bool {
+        self.mmu.is_double_speed()
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        self.cpu.state()
+    }
+
+    // Memory access for debuggers and cheats, never blocked by the PPU or OAM DMA.
+    pub fn read_byte(&self, addr: u16) -> u8 {
+        self.mmu.peek(addr)
+    }
+
+    pub fn write_byte(&mut self, addr: u16, value: u8) {
+        self.mmu.poke(addr, value);
+    }
+
+    pub fn read_range(&self, addr: u16, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| self.mmu.peek(addr.wrapping_add(i as u16)))
+            .collect()
+    }
+
+    pub fn run_frame(&mut self, key_states: impl Into<KeyStates>) -> Result<StopReason, CpuError> {
+        if !self.mid_frame {
+            let keys = self.frame_input(key_states.into());
+            self.mmu.handle_joypad(keys);
+        }
+
         loop {
-            let cycles = self.cpu.step(&mut self.mmu);
-            let frame_ready = self.mmu.tick(cycles);
+            if !self.breakpoints.is_empty() && self.hit_breakpoint() {
+                self.mid_frame = true;
+                return Ok(StopReason::Breakpoint(self.cpu.r.pc));
+            }
+
+            let step = self.step_cpu()?;
+            if step.magic_breakpoint {
+                self.mid_frame = !step.frame_ready;
+                return Ok(StopReason::MagicBreakpoint);
+            }
+            if step.frame_ready {
+                self.mid_frame = false;
+                return Ok(StopReason::FrameReady);
+            }
+        }
+    }
+
+    // Watchdog for automated runs: gives up once `max_cycles` T-cycles passed without a frame,
+    // so a hung ROM can't spin forever. Input and breakpoints are left as they are.
+    pub fn run_until_vblank(&mut self, max_cycles: u64) -> Result<RunOutcome, CpuError> {
+        let limit = self.total_cycles().saturating_add(max_cycles);
+
+        while self.total_cycles() < limit {
+            if self.step_cpu()?.frame_ready {
+                self.mid_frame = false;
+                return Ok(RunOutcome::FrameReady);
+            }
+        }
+
+        Ok(RunOutcome::CycleLimit)
+    }
 
-            if frame_ready {
+    // Runs `n` frames back to back without presenting them, e.g. for headless testing.
+    pub fn run_frames(
+        &mut self,
+        n: usize,
+        key_states: impl Into<KeyStates>,
+    ) -> Result<StopReason, CpuError> {
+        let key_states = key_states.into();
+        let mut reason = StopReason::FrameReady;
+        for _ in 0..n {
+            reason = self.run_frame(key_states)?;
+            if reason != StopReason::FrameReady {
                 break;
             }
         }
+        Ok(reason)
     }
 
     pub fn get_last_frame_buffer(&self) -> [u32; SCREEN_W * SCREEN_H] {
         let mut colors = [0u32; SCREEN_H * SCREEN_W];
 
         for (i, &pix) in self.mmu.get_fb().iter().enumerate() {
-            let c = LCD_PALETTE[pix as usize];
-            colors[i] = c;
+            colors[i] = self.pixel_color(pix);
         }
 
         colors
     }
 
+    // 0x00RRGGBB color of a PPU pixel, CGB pixels are 15-bit BGR.
+    fn pixel_color(&self, pix: u16) -> u32 {
+        if !self.mmu.is_cgb() {
+            return LCD_PALETTE[pix as usize];
+        }
+
+        let channel = |shift: u16| {
+            let c = ((pix >> shift) & 0x1F) as u32;
+            (c << 3) | (c >> 2)
+        };
+        (channel(0) << 16) | (channel(5) << 8) | channel(10)
+    }
+
+    // Tile atlas for debuggers: 384 tiles, 16 per row, as a 128×192 buffer of shades 0–3.
+    pub fn dump_tiles(&self) -> Vec<u8> {
+        self.mmu.dump_tiles()
+    }
+
+    // Full 256×256 background map as shades 0–3, `map` picks 0x9C00 over 0x9800.
+    pub fn dump_tilemap(&self, map: bool) -> Vec<u8> {
+        self.mmu.dump_tilemap(map)
+    }
+
+    // Raw 16 byte SGB command packets (borders, palettes, ...) sent since the last call.
+    // Nothing is applied, a frontend can interpret them itself.
+    pub fn take_sgb_packets(&mut self) -> Vec<[u8; 16]> {
+        self.mmu.take_sgb_packets()
+    }
+
+    // Bytes the game sent over the link cable since the last call.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.mmu.take_serial_output()
+    }
+
+    // Last frame as shades 0 (white) to 3 (black), without any color expansion, for driving
+    // 2-bit displays. CGB colors are mapped to the closest shade by luminance.
+    pub fn frame_indices(&self) -> &[u8; SCREEN_W * SCREEN_H] {
+        self.mmu.frame_shades()
+    }
+
+    // Same frame as `get_last_frame_buffer`, as tightly packed R, G, B, A bytes.
+    pub fn frame_rgba(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(SCREEN_W * SCREEN_H * 4);
+
+        for &pix in self.mmu.get_fb().iter() {
+            let c = self.pixel_color(pix);
+            rgba.extend_from_slice(&[(c >> 16) as u8, (c >> 8) as u8, c as u8, 0xFF]);
+        }
+
+        rgba
+    }
+
     pub fn save(&self) -> Option<Vec<u8>> {
         self.mmu.save()
     }
+
+    // Battery save data, only when it changed since the last call, to checkpoint it periodically.
+    pub fn take_dirty_save(&mut self) -> Option<Vec<u8>> {
+        if self.mmu.take_save_dirty() {
+            self.save()
+        } else {
+            None
+        }
+    }
+
+    // Writes the battery save (RAM and RTC) to `path`, does nothing for carts without a battery.
+    // The data goes to a temporary file first, renamed over `path` once complete, so a crash
+    // mid-write leaves the previous save intact.
+    #[cfg(feature = "std")]
+    pub fn save_to_path<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let Some(data) = self.save() else {
+            return Ok(());
+        };
+
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("sav.tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&tmp_path, path)
+    }
+
+    // Snapshot of the whole machine, the ROM is only referenced by its checksum.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new(self.mmu.rom_checksum());
+        self.cpu.save_state(&mut w);
+        self.mmu.save_state(&mut w);
+        w.finish()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let r = StateReader::new(data, self.mmu.rom_checksum())?;
+
+        // A state that turns out to be broken halfway through must not leave
+        // the emulator half restored, so keep a way back.
+        let backup = self.save_state();
+        if let Err(e) = self.load_components(r) {
+            let r = StateReader::new(&backup, self.mmu.rom_checksum())?;
+            self.load_components(r)?;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn load_components(&mut self, mut r: StateReader) -> Result<(), StateError> {
+        self.cpu.load_state(&mut r)?;
+        self.mmu.load_state(&mut r)?;
+        r.finish()
+    }
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Default)]
 pub struct KeyStates {
     pub a: bool,
     pub b: bool,
@@ -72,3 +582,78 @@ pub struct KeyStates {
     pub left: bool,
     pub right: bool,
 }
+
+impl KeyStates {
+    pub fn from_bits(buttons: Buttons) -> Self {
+        KeyStates {
+            a: buttons.contains(Buttons::A),
+            b: buttons.contains(Buttons::B),
+            select: buttons.contains(Buttons::SELECT),
+            start: buttons.contains(Buttons::START),
+            right: buttons.contains(Buttons::RIGHT),
+            left: buttons.contains(Buttons::LEFT),
+            up: buttons.contains(Buttons::UP),
+            down: buttons.contains(Buttons::DOWN),
+        }
+    }
+
+    pub fn to_bits(self) -> Buttons {
+        [
+            (self.a, Buttons::A),
+            (self.b, Buttons::B),
+            (self.select, Buttons::SELECT),
+            (self.start, Buttons::START),
+            (self.right, Buttons::RIGHT),
+            (self.left, Buttons::LEFT),
+            (self.up, Buttons::UP),
+            (self.down, Buttons::DOWN),
+        ]
+        .into_iter()
+        .filter(|&(pressed, _)| pressed)
+        .fold(Buttons::NONE, |acc, (_, button)| acc | button)
+    }
+}
+
+impl From<Buttons> for KeyStates {
+    fn from(buttons: Buttons) -> Self {
+        KeyStates::from_bits(buttons)
+    }
+}
+
+// Pressed buttons as a single byte, in JOYP order: buttons in the low nibble, d-pad in the high one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Buttons(pub u8);
+
+impl Buttons {
+    pub const NONE: Buttons = Buttons(0);
+    pub const A: Buttons = Buttons(1 << 0);
+    pub const B: Buttons = Buttons(1 << 1);
+    pub const SELECT: Buttons = Buttons(1 << 2);
+    pub const START: Buttons = Buttons(1 << 3);
+    pub const RIGHT: Buttons = Buttons(1 << 4);
+    pub const LEFT: Buttons = Buttons(1 << 5);
+    pub const UP: Buttons = Buttons(1 << 6);
+    pub const DOWN: Buttons = Buttons(1 << 7);
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(self, other: Buttons) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for Buttons {
+    fn bitor_assign(&mut self, rhs: Buttons) {
+        self.0 |= rhs.0;
+    }
+}
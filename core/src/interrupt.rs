@@ -29,22 +29,16 @@ impl Interrupt {
     }
 }
 
+// Lower bits win: VBlank, Stat, Timer, Serial, Joypad.
 #[inline]
 pub fn highest_priority(pending_interrupt: u8) -> Option<Interrupt> {
-    if pending_interrupt & (1 << Interrupt::VBlank as u8) != 0 {
-        return Some(Interrupt::VBlank);
-    }
-    if pending_interrupt & (1 << Interrupt::Stat as u8) != 0 {
-        return Some(Interrupt::Stat);
-    }
-    if pending_interrupt & (1 << Interrupt::Timer as u8) != 0 {
-        return Some(Interrupt::Timer);
-    }
-    if pending_interrupt & (1 << Interrupt::Serial as u8) != 0 {
-        return Some(Interrupt::Serial);
-    }
-    if pending_interrupt & (1 << Interrupt::Joypad as u8) != 0 {
-        return Some(Interrupt::Joypad);
-    }
-    None
+    [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ]
+    .into_iter()
+    .find(|interrupt| pending_interrupt & interrupt.bit() != 0)
 }
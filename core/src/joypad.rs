@@ -1,10 +1,35 @@
-use crate::{gameboy::KeyStates, interrupt::Interrupt};
+use alloc::vec::Vec;
+
+use crate::{
+    gameboy::KeyStates,
+    interrupt::Interrupt,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 pub const JOYP_ADDR: u16 = 0xFF00;
 
 pub struct Joypad {
     joyp: u8, // OxFF00 — P1/JOYP: Joypad
     states: KeyStates,
+    sgb: SgbTransfer,
+    sgb_packets: Vec<[u8; SGB_PACKET_LEN]>,
+}
+
+const SGB_PACKET_LEN: usize = 16;
+const SGB_PACKET_BITS: u8 = SGB_PACKET_LEN as u8 * 8;
+
+// SGB command packets are sent by pulsing P14/P15, one bit per pulse, LSB first:
+//   P14 & P15 low — Reset, starts a packet
+//   P14 low       — 0
+//   P15 low       — 1
+//   P14 & P15 high between every pulse, a final 0 bit ends the 128 bit packet.
+// ref: [https://gbdev.io/pandocs/SGB_Command_Packet.html]
+#[derive(Default)]
+struct SgbTransfer {
+    active: bool,
+    released: bool, // Both lines went back high since the last pulse
+    bits: u8,
+    packet: [u8; SGB_PACKET_LEN],
 }
 
 // JOYP [ - - 5 4 3 2 1 0 ]
@@ -20,43 +45,79 @@ impl Joypad {
         Joypad {
             joyp: 0xFF,
             states: KeyStates::default(),
+            sgb: SgbTransfer::default(),
+            sgb_packets: Vec::new(),
         }
     }
 
+    // The interrupt fires on a high to low edge of any P1 input line, so only presses
+    // on a selected group count, the same way the CPU would read them.
     pub fn tick(&mut self, new_states: KeyStates) -> u8 {
-        let mut interruts = 0;
-
-        if (new_states.a && !self.states.a)
-            || (new_states.b && !self.states.b)
-            || (new_states.start && !self.states.start)
-            || (new_states.select && !self.states.select)
-            || (new_states.up && !self.states.up)
-            || (new_states.down && !self.states.down)
-            || (new_states.left && !self.states.left)
-            || (new_states.right && !self.states.right)
-        {
-            interruts |= Interrupt::Joypad.bit();
-        }
-
+        let before = self.build_joyp();
         self.states = new_states;
+        let after = self.build_joyp();
 
-        interruts
+        if before & !after & 0x0F != 0 {
+            Interrupt::Joypad.bit()
+        } else {
+            0
+        }
     }
 
     pub fn rb(&self, addr: u16) -> u8 {
         match addr {
-            JOYP_ADDR => self.build_joyp(),
+            JOYP_ADDR => self.build_joyp() | 0xC0, // Bits 6-7 are unused and read 1
             _ => unreachable!(),
         }
     }
 
     pub fn wb(&mut self, addr: u16, value: u8) {
         match addr {
-            JOYP_ADDR => self.joyp = value & 0x30, // Drop lower nibble
+            JOYP_ADDR => {
+                self.joyp = value & 0x30; // Drop lower nibble
+                self.sgb_pulse();
+            }
             _ => unreachable!(),
         }
     }
 
+    fn sgb_pulse(&mut self) {
+        let sgb = &mut self.sgb;
+        match self.joyp {
+            0x00 => {
+                *sgb = SgbTransfer::default();
+                sgb.active = true;
+            }
+            0x30 => sgb.released = true,
+            _ if sgb.active && sgb.released => {
+                sgb.released = false;
+                let bit = self.joyp == 0x10; // P15 low
+                if sgb.bits == SGB_PACKET_BITS {
+                    if !bit {
+                        self.sgb_packets.push(sgb.packet);
+                    }
+                    sgb.active = false;
+                } else {
+                    if bit {
+                        sgb.packet[sgb.bits as usize / 8] |= 1 << (sgb.bits % 8);
+                    }
+                    sgb.bits += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Command packets the game sent to a Super Game Boy since the last call.
+    pub fn take_sgb_packets(&mut self) -> Vec<[u8; SGB_PACKET_LEN]> {
+        core::mem::take(&mut self.sgb_packets)
+    }
+
+    // True while any of the selected input lines is pulled low.
+    pub fn any_pressed(&self) -> bool {
+        self.build_joyp() & 0x0F != 0x0F
+    }
+
     fn build_joyp(&self) -> u8 {
         match self.get_select_mode() {
             Mode::Buttons => self.joyp | self.build_buttons(),
@@ -91,6 +152,29 @@ impl Joypad {
     }
 }
 
+// Key states aren't kept, they are fed in by the frontend every frame.
+// Finished SGB packets aren't either, the frontend takes them as they arrive.
+impl Snapshot for Joypad {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.joyp);
+        w.bool(self.sgb.active);
+        w.bool(self.sgb.released);
+        w.u8(self.sgb.bits);
+        w.bytes(&self.sgb.packet);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.joyp = r.u8()?;
+        self.sgb.active = r.bool()?;
+        self.sgb.released = r.bool()?;
+        self.sgb.bits = r.u8()?;
+        if self.sgb.bits > SGB_PACKET_BITS {
+            return Err(StateError::Invalid);
+        }
+        r.bytes(&mut self.sgb.packet)
+    }
+}
+
 enum Mode {
     Buttons,
     DPad,
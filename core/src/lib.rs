@@ -1,3 +1,13 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::upper_case_acronyms)]
+// The MBCs keep their enable checks inside the match arms.
+#![allow(clippy::collapsible_match)]
+
+extern crate alloc;
+
+mod apu;
+#[cfg(feature = "archive")]
+mod archive;
 pub mod cart;
 pub mod cpu;
 pub mod gameboy;
@@ -6,4 +16,10 @@ mod joypad;
 mod mbc;
 pub mod mmu;
 mod ppu;
+mod serial;
+pub mod state;
 mod timer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+mod wav;
@@ -1,15 +1,26 @@
-use crate::mbc::MemoryController;
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    mbc::{MemoryController, ram_index, wrap_bank},
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 pub struct Mbc1 {
     rom: Vec<u8>,
     ram: Vec<u8>,
     has_ram: bool,
     has_battery: bool,
+    multicart: bool, // MBC1M, bit 4 of the bank register isn't wired to the ROM
     // Registers
     ram_enable: bool, // [0x0000 – 0x1FFF] — Enable ram by writing $A to any address
     rom_bank: u8,     // [0x2000 – 0x3FFF]
     ram_bank_or_upper_rom: u8, // [0x4000 – 0x5FFF]
     banking_mode: u8, // [0x6000 – 0x7FFF] — 0: ROM, 1: RAM
+    dirty: bool,      // RAM written since the last take_dirty
+    // Offsets of the mapped banks, recomputed when a bank register changes
+    zero_base: usize,
+    rom_base: usize,
+    ram_base: usize,
 }
 
 impl Mbc1 {
@@ -21,29 +32,58 @@ impl Mbc1 {
         ram_size: u32,
         has_ram: bool,
         has_battery: bool,
+        multicart: bool,
         save_data: Option<Vec<u8>>,
     ) -> Self {
-        Mbc1 {
+        let ram = save_data.unwrap_or_else(|| vec![0; ram_size as usize]);
+        // Headers can declare a cart with RAM and a RAM size of 0.
+        let has_ram = has_ram && !ram.is_empty();
+        let mut mbc = Mbc1 {
             rom,
-            ram: save_data.unwrap_or_else(|| vec![0; ram_size as usize]),
+            ram,
             has_ram,
             has_battery,
+            multicart,
             ram_enable: false,
             rom_bank: 0,
             ram_bank_or_upper_rom: 0,
             banking_mode: 0,
-        }
+            dirty: false,
+            zero_base: 0,
+            rom_base: 0,
+            ram_base: 0,
+        };
+        mbc.update_banks();
+        mbc
+    }
+
+    fn update_banks(&mut self) {
+        self.zero_base = self.zero_bank_addr_start();
+        self.rom_base = self.rom_bank_addr_start();
+        self.ram_base = self.ram_bank_addr_start();
     }
 
+    // The upper bits always apply to 0x4000 – 0x7FFF, only the 5 bit register maps 0 to 1,
+    // so banks 0x20/0x40/0x60 can't be selected there.
     fn selected_rom_bank(&self) -> u16 {
-        let lower_bank = u16::max(self.rom_bank as u16, 1);
-        if self.banking_mode == 1 {
-            lower_bank
+        let lower_bank = u16::max(self.rom_bank as u16, 1) & ((1 << self.upper_shift()) - 1);
+        ((self.ram_bank_or_upper_rom as u16) << self.upper_shift()) | lower_bank
+    }
+
+    // In mode 1 the upper bits also remap 0x0000 – 0x3FFF to bank 0x20/0x40/0x60 on large ROMs.
+    fn selected_zero_bank(&self) -> u16 {
+        if self.banking_mode == 0 {
+            0
         } else {
-            ((self.ram_bank_or_upper_rom as u16) << 5) | lower_bank
+            (self.ram_bank_or_upper_rom as u16) << self.upper_shift()
         }
     }
 
+    // Where the upper bits land in the bank number, multicarts skip bit 4 of the bank register.
+    fn upper_shift(&self) -> u16 {
+        if self.multicart { 4 } else { 5 }
+    }
+
     fn selected_ram_bank(&self) -> u16 {
         if self.banking_mode == 0 {
             0
@@ -53,30 +93,52 @@ impl Mbc1 {
     }
 
     fn rom_bank_addr_start(&self) -> usize {
-        let selected_bank = self.selected_rom_bank();
-        Mbc1::ROM_BANK_SIZE * selected_bank as usize
+        let selected_bank = self.selected_rom_bank() as usize;
+        Mbc1::ROM_BANK_SIZE * wrap_bank(selected_bank, self.rom.len(), Mbc1::ROM_BANK_SIZE)
+    }
+
+    fn zero_bank_addr_start(&self) -> usize {
+        let selected_bank = self.selected_zero_bank() as usize;
+        Mbc1::ROM_BANK_SIZE * wrap_bank(selected_bank, self.rom.len(), Mbc1::ROM_BANK_SIZE)
     }
 
+    // 8 KiB carts ignore the bank bits in mode 1.
     fn ram_bank_addr_start(&self) -> usize {
-        let selected_bank = self.selected_ram_bank();
-        Mbc1::RAM_BANK_SIZE * selected_bank as usize
+        let selected_bank = self.selected_ram_bank() as usize;
+        Mbc1::RAM_BANK_SIZE * wrap_bank(selected_bank, self.ram.len(), Mbc1::RAM_BANK_SIZE)
+    }
+}
+
+impl Snapshot for Mbc1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.vec(&self.ram);
+        w.bool(self.ram_enable);
+        w.u8(self.rom_bank);
+        w.u8(self.ram_bank_or_upper_rom);
+        w.u8(self.banking_mode);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.vec(&mut self.ram)?;
+        self.ram_enable = r.bool()?;
+        self.rom_bank = r.u8()? & 0x1F;
+        self.ram_bank_or_upper_rom = r.u8()? & 0x03;
+        self.banking_mode = r.u8()? & 0x01;
+        self.update_banks();
+        Ok(())
     }
 }
 
 impl MemoryController for Mbc1 {
-    fn rb(&mut self, addr: u16) -> u8 {
+    fn rb(&self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x3FFF => match self.banking_mode {
-                0x00 => self.rom[addr as usize],
-                0x01 => self.rom[addr as usize + self.rom_bank_addr_start()],
-                _ => unreachable!(),
-            },
-            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + self.rom_bank_addr_start()],
+            0x0000..=0x3FFF => self.rom[addr as usize + self.zero_base],
+            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + self.rom_base],
             0xA000..=0xBFFF => {
                 if !self.has_ram || !self.ram_enable {
                     0xFF
                 } else {
-                    self.ram[(addr - 0xA000) as usize + self.ram_bank_addr_start()]
+                    self.ram[ram_index(addr, self.ram_base, self.ram.len())]
                 }
             }
             _ => 0xFF,
@@ -86,13 +148,23 @@ impl MemoryController for Mbc1 {
     fn wb(&mut self, addr: u16, value: u8) {
         match addr {
             0x0000..=0x1FFF => self.ram_enable = value & 0x0F == 0x0A,
-            0x2000..=0x3FFF => self.rom_bank = value & 0x1F,
-            0x4000..=0x5FFF => self.ram_bank_or_upper_rom = value & 0x03,
-            0x6000..=0x7FFF => self.banking_mode = value & 0x01,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0x1F;
+                self.update_banks();
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank_or_upper_rom = value & 0x03;
+                self.update_banks();
+            }
+            0x6000..=0x7FFF => {
+                self.banking_mode = value & 0x01;
+                self.update_banks();
+            }
             0xA000..=0xBFFF => {
-                if self.ram_enable {
-                    let bank_start = self.ram_bank_addr_start();
-                    self.ram[(addr - 0xA000) as usize + bank_start] = value
+                if self.has_ram && self.ram_enable {
+                    let index = ram_index(addr, self.ram_base, self.ram.len());
+                    self.ram[index] = value;
+                    self.dirty = true;
                 }
             }
             _ => (),
@@ -102,4 +174,16 @@ impl MemoryController for Mbc1 {
     fn save(&self) -> Option<Vec<u8>> {
         self.has_battery.then_some(self.ram.clone())
     }
+
+    fn take_dirty(&mut self) -> bool {
+        core::mem::take(&mut self.dirty)
+    }
+
+    fn reset(&mut self) {
+        self.ram_enable = false;
+        self.rom_bank = 0;
+        self.ram_bank_or_upper_rom = 0;
+        self.banking_mode = 0;
+        self.update_banks();
+    }
 }
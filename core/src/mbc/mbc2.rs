@@ -1,4 +1,9 @@
-use crate::mbc::MemoryController;
+use alloc::vec::Vec;
+
+use crate::{
+    mbc::{MemoryController, wrap_bank},
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 pub struct Mbc2 {
     rom: Vec<u8>,
@@ -7,6 +12,8 @@ pub struct Mbc2 {
     // Registers
     ram_enable: bool, // [0x0000 – 0x1FFF] — RAM Enable, ROM Bank Number
     rom_bank: u8,     // [0x0000 – 0x1FFF] — RAM Enable, ROM Bank Number
+    dirty: bool,      // RAM written since the last take_dirty
+    rom_base: usize,  // Offset of the mapped bank, recomputed when the bank register changes
 }
 
 impl Mbc2 {
@@ -15,27 +22,56 @@ impl Mbc2 {
     pub fn new(rom: Vec<u8>, has_battery: bool, save_data: Option<Vec<u8>>) -> Self {
         let mut ram = [0u8; 0x200];
         if let Some(data) = save_data {
-            ram.copy_from_slice(&data[..0x200.min(data.len())]);
+            // Saves from other emulators may be shorter, only the low nibbles matter.
+            let len = ram.len().min(data.len());
+            for (dst, &src) in ram.iter_mut().zip(&data[..len]) {
+                *dst = src | 0xF0;
+            }
         }
-        Mbc2 {
+        let mut mbc = Mbc2 {
             rom,
             ram,
             has_battery,
             ram_enable: false,
             rom_bank: 1,
-        }
+            dirty: false,
+            rom_base: 0,
+        };
+        mbc.update_banks();
+        mbc
+    }
+
+    fn update_banks(&mut self) {
+        self.rom_base = self.rom_bank_addr_start();
     }
 
     fn rom_bank_addr_start(&self) -> usize {
-        Mbc2::ROM_BANK_SIZE * self.rom_bank as usize
+        let bank = wrap_bank(self.rom_bank as usize, self.rom.len(), Mbc2::ROM_BANK_SIZE);
+        Mbc2::ROM_BANK_SIZE * bank
+    }
+}
+
+impl Snapshot for Mbc2 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.bool(self.ram_enable);
+        w.u8(self.rom_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.ram)?;
+        self.ram_enable = r.bool()?;
+        self.rom_bank = u8::max(r.u8()? & 0x0F, 1);
+        self.update_banks();
+        Ok(())
     }
 }
 
 impl MemoryController for Mbc2 {
-    fn rb(&mut self, addr: u16) -> u8 {
+    fn rb(&self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x3FFF => self.rom[addr as usize],
-            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + self.rom_bank_addr_start()],
+            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + self.rom_base],
             0xA000..=0xA1FF => {
                 if self.ram_enable {
                     self.ram[(addr - 0xA000) as usize] | 0xF0 // MBC2 has 4 bit ram
@@ -59,18 +95,21 @@ impl MemoryController for Mbc2 {
             0x0000..=0x3FFF => {
                 if addr & 0x100 != 0 {
                     self.rom_bank = u8::max(value & 0x0F, 1);
+                    self.update_banks();
                 } else {
                     self.ram_enable = value & 0x0F == 0x0A
                 }
             }
             0xA000..=0xA1FF => {
                 if self.ram_enable {
-                    self.ram[(addr - 0xA000) as usize] = value | 0xF0
+                    self.ram[(addr - 0xA000) as usize] = value | 0xF0;
+                    self.dirty = true;
                 }
             }
             0xA200..=0xBFFF => {
                 if self.ram_enable {
-                    self.ram[((addr - 0xA200) & 0x1FF) as usize] = value | 0xF0
+                    self.ram[((addr - 0xA200) & 0x1FF) as usize] = value | 0xF0;
+                    self.dirty = true;
                 }
             }
             _ => (),
@@ -80,4 +119,14 @@ impl MemoryController for Mbc2 {
     fn save(&self) -> Option<Vec<u8>> {
         self.has_battery.then_some(self.ram.to_vec())
     }
+
+    fn take_dirty(&mut self) -> bool {
+        core::mem::take(&mut self.dirty)
+    }
+
+    fn reset(&mut self) {
+        self.ram_enable = false;
+        self.rom_bank = 1;
+        self.update_banks();
+    }
 }
@@ -1,4 +1,13 @@
-use crate::mbc::{MemoryController, rtc::RTC};
+use alloc::{boxed::Box, vec, vec::Vec};
+
+use crate::{
+    mbc::{
+        MemoryController, ram_index,
+        rtc::{Clock, RTC, RTC_SAVE_SIZE},
+        wrap_bank,
+    },
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 pub struct Mbc3 {
     rom: Vec<u8>,
@@ -11,6 +20,10 @@ pub struct Mbc3 {
     rom_bank: u8,           // [0x2000 – 0x3FFF]
     ram_bank_rtc_register: u8, // [0x4000 – 0x5FFF] — $00-$07 The corresponding RAM Bank. $08-$0C The corresponding RTC Register
     rtc: Option<RTC>,
+    dirty: bool, // RAM or RTC registers written since the last take_dirty
+    // Offsets of the mapped banks, recomputed when a bank register changes
+    rom_base: usize,
+    ram_base: usize,
 }
 
 impl Mbc3 {
@@ -24,22 +37,50 @@ impl Mbc3 {
         has_battery: bool,
         has_timer: bool,
         save_data: Option<Vec<u8>>,
+        clock: Box<dyn Clock>,
     ) -> Self {
-        Mbc3 {
+        let ram_size = ram_size as usize;
+        let mut ram = vec![0; ram_size];
+        let mut rtc = has_timer.then(|| RTC::init(clock));
+
+        // Battery saves are the RAM contents, followed by the RTC state for carts with a timer.
+        if let Some(data) = save_data {
+            let ram_len = ram_size.min(data.len());
+            ram[..ram_len].copy_from_slice(&data[..ram_len]);
+
+            if let Some(rtc) = rtc.as_mut() {
+                rtc.restore(&data[ram_len..]);
+            }
+        }
+
+        // Headers can declare a cart with RAM and a RAM size of 0.
+        let has_ram = has_ram && !ram.is_empty();
+        let mut mbc = Mbc3 {
             rom,
-            ram: save_data.unwrap_or_else(|| vec![0; ram_size as usize]),
+            ram,
             has_ram,
             has_battery,
             has_timer,
             ram_timer_enable: false,
             rom_bank: 1,
             ram_bank_rtc_register: 0,
-            rtc: has_timer.then(RTC::init),
-        }
+            rtc,
+            dirty: false,
+            rom_base: 0,
+            ram_base: 0,
+        };
+        mbc.update_banks();
+        mbc
+    }
+
+    fn update_banks(&mut self) {
+        self.rom_base = self.rom_bank_addr_start();
+        self.ram_base = self.ram_bank_addr_start();
     }
 
     fn rom_bank_addr_start(&self) -> usize {
-        Mbc3::ROM_BANK_SIZE * self.rom_bank as usize
+        let bank = wrap_bank(self.rom_bank as usize, self.rom.len(), Mbc3::ROM_BANK_SIZE);
+        Mbc3::ROM_BANK_SIZE * bank
     }
 
     fn ram_bank_addr_start(&self) -> usize {
@@ -47,20 +88,45 @@ impl Mbc3 {
     }
 }
 
+impl Snapshot for Mbc3 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.vec(&self.ram);
+        w.bool(self.ram_timer_enable);
+        w.u8(self.rom_bank);
+        w.u8(self.ram_bank_rtc_register);
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.vec(&mut self.ram)?;
+        self.ram_timer_enable = r.bool()?;
+        self.rom_bank = u8::max(r.u8()? & 0x7F, 1);
+        self.ram_bank_rtc_register = r.u8()? & 0x0F;
+        self.update_banks();
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_state(r)?;
+        }
+        Ok(())
+    }
+}
+
 impl MemoryController for Mbc3 {
-    fn rb(&mut self, addr: u16) -> u8 {
+    fn rb(&self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x3FFF => self.rom[addr as usize],
-            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + self.rom_bank_addr_start()],
+            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + self.rom_base],
             0xA000..=0xBFFF => {
-                if !self.has_ram || !self.ram_timer_enable {
+                if !self.ram_timer_enable {
                     0xFF
                 } else {
                     let ram_selected = self.ram_bank_rtc_register <= 0x07;
 
-                    if ram_selected {
-                        self.ram[(addr - 0xA000) as usize + self.ram_bank_addr_start()]
-                    } else if self.has_timer
+                    if ram_selected && self.has_ram {
+                        self.ram[ram_index(addr, self.ram_base, self.ram.len())]
+                    } else if !ram_selected
+                        && self.has_timer
                         && let Some(rtc) = &self.rtc
                     {
                         rtc.read_register(self.ram_bank_rtc_register)
@@ -82,19 +148,28 @@ impl MemoryController for Mbc3 {
                     self.ram_timer_enable = false
                 }
             }
-            0x2000..=0x3FFF => self.rom_bank = u8::max(value & 0x7F, 1),
-            0x4000..=0x5FFF => self.ram_bank_rtc_register = value & 0x0F,
+            0x2000..=0x3FFF => {
+                self.rom_bank = u8::max(value & 0x7F, 1);
+                self.update_banks();
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank_rtc_register = value & 0x0F;
+                self.update_banks();
+            }
             0xA000..=0xBFFF => {
-                if self.has_ram && self.ram_timer_enable {
+                if self.ram_timer_enable {
                     let ram_selected = self.ram_bank_rtc_register <= 0x07;
 
-                    if ram_selected {
-                        let bank_start = self.ram_bank_addr_start();
-                        self.ram[(addr - 0xA000) as usize + bank_start] = value
-                    } else if self.has_timer
+                    if ram_selected && self.has_ram {
+                        let index = ram_index(addr, self.ram_base, self.ram.len());
+                        self.ram[index] = value;
+                        self.dirty = true;
+                    } else if !ram_selected
+                        && self.has_timer
                         && let Some(rtc) = &mut self.rtc
                     {
-                        rtc.write_regisetr(self.ram_bank_rtc_register, value)
+                        rtc.write_register(self.ram_bank_rtc_register, value);
+                        self.dirty = true;
                     }
                 }
             }
@@ -111,6 +186,27 @@ impl MemoryController for Mbc3 {
     }
 
     fn save(&self) -> Option<Vec<u8>> {
-        self.has_battery.then_some(self.ram.clone())
+        if !self.has_battery {
+            return None;
+        }
+
+        let mut data = Vec::with_capacity(self.ram.len() + RTC_SAVE_SIZE);
+        data.extend_from_slice(&self.ram);
+        if let Some(rtc) = &self.rtc {
+            data.extend(rtc.save());
+        }
+
+        Some(data)
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        core::mem::take(&mut self.dirty)
+    }
+
+    fn reset(&mut self) {
+        self.ram_timer_enable = false;
+        self.rom_bank = 1;
+        self.ram_bank_rtc_register = 0;
+        self.update_banks();
     }
 }
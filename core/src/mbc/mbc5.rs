@@ -0,0 +1,146 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    mbc::{MemoryController, ram_index, wrap_bank},
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    has_ram: bool,
+    has_battery: bool,
+    has_rumble: bool,
+    // Registers
+    ram_enable: bool, // [0x0000 – 0x1FFF] — Enable ram by writing $0A
+    rom_bank: u16,    // [0x2000 – 0x2FFF] — Lower 8 bits, [0x3000 – 0x3FFF] — 9th bit
+    ram_bank: u8,     // [0x4000 – 0x5FFF] — $00-$0F, bit 3 drives the rumble motor on rumble carts
+    dirty: bool,      // RAM written since the last take_dirty
+    // Offsets of the mapped banks, recomputed when a bank register changes
+    rom_base: usize,
+    ram_base: usize,
+}
+
+impl Mbc5 {
+    const ROM_BANK_SIZE: usize = 16 * 1024;
+    const RAM_BANK_SIZE: usize = 8 * 1024;
+
+    pub fn new(
+        rom: Vec<u8>,
+        ram_size: u32,
+        has_ram: bool,
+        has_battery: bool,
+        has_rumble: bool,
+        save_data: Option<Vec<u8>>,
+    ) -> Self {
+        let ram = save_data.unwrap_or_else(|| vec![0; ram_size as usize]);
+        // Headers can declare a cart with RAM and a RAM size of 0.
+        let has_ram = has_ram && !ram.is_empty();
+        let mut mbc = Mbc5 {
+            rom,
+            ram,
+            has_ram,
+            has_battery,
+            has_rumble,
+            ram_enable: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            dirty: false,
+            rom_base: 0,
+            ram_base: 0,
+        };
+        mbc.update_banks();
+        mbc
+    }
+
+    fn update_banks(&mut self) {
+        self.rom_base = self.rom_bank_addr_start();
+        self.ram_base = self.ram_bank_addr_start();
+    }
+
+    fn rom_bank_addr_start(&self) -> usize {
+        // Unlike MBC1/3, bank 0 can be mapped to 0x4000 – 0x7FFF.
+        let bank = wrap_bank(self.rom_bank as usize, self.rom.len(), Mbc5::ROM_BANK_SIZE);
+        Mbc5::ROM_BANK_SIZE * bank
+    }
+
+    fn ram_bank_addr_start(&self) -> usize {
+        // The rumble motor is wired to bit 3, leaving 3 bits for the RAM bank.
+        let bank = if self.has_rumble {
+            self.ram_bank & 0x07
+        } else {
+            self.ram_bank
+        };
+        Mbc5::RAM_BANK_SIZE * wrap_bank(bank as usize, self.ram.len(), Mbc5::RAM_BANK_SIZE)
+    }
+}
+
+impl Snapshot for Mbc5 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.vec(&self.ram);
+        w.bool(self.ram_enable);
+        w.u16(self.rom_bank);
+        w.u8(self.ram_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.vec(&mut self.ram)?;
+        self.ram_enable = r.bool()?;
+        self.rom_bank = r.u16()? & 0x1FF;
+        self.ram_bank = r.u8()? & 0x0F;
+        self.update_banks();
+        Ok(())
+    }
+}
+
+impl MemoryController for Mbc5 {
+    fn rb(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom[addr as usize],
+            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + self.rom_base],
+            0xA000..=0xBFFF if self.has_ram && self.ram_enable => {
+                self.ram[ram_index(addr, self.ram_base, self.ram.len())]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn wb(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enable = value == 0x0A,
+            0x2000..=0x2FFF => {
+                self.rom_bank = (self.rom_bank & 0x100) | value as u16;
+                self.update_banks();
+            }
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8);
+                self.update_banks();
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank = value & 0x0F;
+                self.update_banks();
+            }
+            0xA000..=0xBFFF if self.has_ram && self.ram_enable => {
+                let index = ram_index(addr, self.ram_base, self.ram.len());
+                self.ram[index] = value;
+                self.dirty = true;
+            }
+            _ => (),
+        }
+    }
+
+    fn save(&self) -> Option<Vec<u8>> {
+        self.has_battery.then_some(self.ram.clone())
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        core::mem::take(&mut self.dirty)
+    }
+
+    fn reset(&mut self) {
+        self.ram_enable = false;
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.update_banks();
+    }
+}
@@ -1,27 +1,121 @@
 pub mod mbc1;
 pub mod mbc2;
 pub mod mbc3;
+pub mod mbc5;
 pub mod rom_only;
 pub mod rtc;
 
-pub trait MemoryController {
-    fn rb(&mut self, addr: u16) -> u8;
+#[cfg(feature = "dyn-mbc")]
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::{
+    mbc::{mbc1::Mbc1, mbc2::Mbc2, mbc3::Mbc3, mbc5::Mbc5, rom_only::RomOnly},
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+// Bank numbers wrap around the banks actually present, the unused high bits aren't wired.
+fn wrap_bank(bank: usize, len: usize, bank_size: usize) -> usize {
+    bank % (len / bank_size).max(1)
+}
+
+// RAM smaller than its 8 KiB window, like the 2 KiB carts, mirrors across it.
+// Empty RAM counts as no RAM, the MBCs never get here with `len` 0.
+fn ram_index(addr: u16, base: usize, len: usize) -> usize {
+    ((addr - 0xA000) as usize + base) & (len - 1)
+}
+
+pub trait MemoryController: Snapshot {
+    fn rb(&self, addr: u16) -> u8;
     fn wb(&mut self, addr: u16, value: u8);
     fn save(&self) -> Option<Vec<u8>>;
+
+    // True when the saved data changed since the last call, clears the flag.
+    fn take_dirty(&mut self) -> bool {
+        false
+    }
+
+    // Back to power-on banking registers, RAM contents are kept.
+    fn reset(&mut self) {}
 }
 
-pub struct Missing;
+// What the cart holds. Every memory access goes through it, so by default it's matched
+// statically. The `dyn-mbc` feature trades that for a vtable and a smaller binary.
+#[cfg(not(feature = "dyn-mbc"))]
+pub type Controller = Mbc;
+#[cfg(feature = "dyn-mbc")]
+pub type Controller = Box<dyn MemoryController>;
+
+// Mbc2 keeps its 512 half-bytes inline, there's only ever one of these so the size is fine.
+#[allow(clippy::large_enum_variant)]
+pub enum Mbc {
+    RomOnly(RomOnly),
+    Mbc1(Mbc1),
+    Mbc2(Mbc2),
+    Mbc3(Mbc3),
+    Mbc5(Mbc5),
+}
 
-impl MemoryController for Missing {
-    fn rb(&mut self, _: u16) -> u8 {
-        panic!("Unimplemented cartridge type")
+impl Mbc {
+    #[cfg(not(feature = "dyn-mbc"))]
+    pub fn into_controller(self) -> Controller {
+        self
     }
 
-    fn wb(&mut self, _: u16, _: u8) {
-        panic!("Unimplemented cartridge type")
+    #[cfg(feature = "dyn-mbc")]
+    pub fn into_controller(self) -> Controller {
+        match self {
+            Mbc::RomOnly(mbc) => Box::new(mbc),
+            Mbc::Mbc1(mbc) => Box::new(mbc),
+            Mbc::Mbc2(mbc) => Box::new(mbc),
+            Mbc::Mbc3(mbc) => Box::new(mbc),
+            Mbc::Mbc5(mbc) => Box::new(mbc),
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($self:ident, $mbc:ident => $call:expr) => {
+        match $self {
+            Mbc::RomOnly($mbc) => $call,
+            Mbc::Mbc1($mbc) => $call,
+            Mbc::Mbc2($mbc) => $call,
+            Mbc::Mbc3($mbc) => $call,
+            Mbc::Mbc5($mbc) => $call,
+        }
+    };
+}
+
+impl Snapshot for Mbc {
+    fn save_state(&self, w: &mut StateWriter) {
+        dispatch!(self, mbc => mbc.save_state(w))
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        dispatch!(self, mbc => mbc.load_state(r))
+    }
+}
+
+impl MemoryController for Mbc {
+    #[inline]
+    fn rb(&self, addr: u16) -> u8 {
+        dispatch!(self, mbc => mbc.rb(addr))
+    }
+
+    #[inline]
+    fn wb(&mut self, addr: u16, value: u8) {
+        dispatch!(self, mbc => mbc.wb(addr, value))
     }
 
     fn save(&self) -> Option<Vec<u8>> {
-        None
+        dispatch!(self, mbc => mbc.save())
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        dispatch!(self, mbc => mbc.take_dirty())
+    }
+
+    fn reset(&mut self) {
+        dispatch!(self, mbc => mbc.reset())
     }
 }
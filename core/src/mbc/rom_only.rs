@@ -1,4 +1,9 @@
-use crate::mbc::MemoryController;
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    mbc::{MemoryController, ram_index},
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 pub struct RomOnly {
     rom: Vec<u8>,
@@ -14,11 +19,22 @@ impl RomOnly {
     }
 }
 
+impl Snapshot for RomOnly {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.vec(&self.eram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.vec(&mut self.eram)
+    }
+}
+
 impl MemoryController for RomOnly {
-    fn rb(&mut self, addr: u16) -> u8 {
+    fn rb(&self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x7FFF => self.rom[addr as usize],
-            0xA000..=0xBFFF => self.eram[(addr - 0xA000) as usize],
+            0xA000..=0xBFFF if self.eram.is_empty() => 0xFF,
+            0xA000..=0xBFFF => self.eram[ram_index(addr, 0, self.eram.len())],
             _ => unreachable!(),
         }
     }
@@ -26,7 +42,11 @@ impl MemoryController for RomOnly {
     fn wb(&mut self, addr: u16, value: u8) {
         match addr {
             0x0000..=0x7FFF => (),
-            0xA000..=0xBFFF => self.eram[(addr - 0xA000) as usize] = value,
+            0xA000..=0xBFFF if self.eram.is_empty() => (),
+            0xA000..=0xBFFF => {
+                let index = ram_index(addr, 0, self.eram.len());
+                self.eram[index] = value
+            }
             _ => unreachable!(),
         }
     }
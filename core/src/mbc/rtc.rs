@@ -1,56 +1,142 @@
-pub struct RTC {
-    startup: u64,
-    rtc_s: u8,  // $08 	Seconds	0-59 ($00-$3B)
-    rtc_m: u8,  // $09	Minutes	0-59 ($00-$3B)
-    rtc_h: u8,  // $0A	Hours	0-23 ($00-$17)
-    rtc_dl: u8, // $0B	Lower 8 bits of Day Counter	($00-$FF)
-    rtc_dh: u8, // $0C
-    latch: bool,
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+// Size of the RTC footer appended to the battery save (VBA-M / BGB format):
+// 5 x u32 live registers, 5 x u32 latched registers, u64 unix timestamp.
+pub const RTC_SAVE_SIZE: usize = 48;
+// Older emulators write the timestamp as a u32.
+const RTC_SAVE_SIZE_LEGACY: usize = 44;
+
+// DH [ 7 6 - - - - - 0 ]
+// 7 - Day counter carry: set when the day counter overflows, stays set until written
+// 6 - Halt: 0 = Active, 1 = Stop timer
+// 0 - Upper 1 bit of the day counter
+const DH_DAY_MSB: u8 = 0x01;
+const DH_HALT: u8 = 0x40;
+const DH_CARRY: u8 = 0x80;
+
+#[derive(Clone, Copy, Default)]
+struct Registers {
+    s: u8,  // $08 	Seconds	0-59 ($00-$3B)
+    m: u8,  // $09	Minutes	0-59 ($00-$3B)
+    h: u8,  // $0A	Hours	0-23 ($00-$17)
+    dl: u8, // $0B	Lower 8 bits of Day Counter	($00-$FF)
+    dh: u8, // $0C  Upper 1 bit of Day Counter, Carry Bit, Halt Flag
 }
 
-#[derive(Debug)]
-pub struct Counters {
-    seconds: u8,
-    minutes: u8,
-    hours: u8,
-    days: u64,
+impl Registers {
+    fn advance(&mut self, secs: u64) {
+        if secs == 0 || self.dh & DH_HALT != 0 {
+            return;
+        }
+
+        let days = ((self.dh & DH_DAY_MSB) as u64) << 8 | self.dl as u64;
+        let total = self.s as u64 + self.m as u64 * 60 + self.h as u64 * 3600 + days * 86400 + secs;
+
+        let days = total / 86400;
+        if days > 0x1FF {
+            self.dh |= DH_CARRY;
+        }
+        let days = days % 512;
+
+        self.s = (total % 60) as u8;
+        self.m = ((total % 3600) / 60) as u8;
+        self.h = ((total % 86400) / 3600) as u8;
+        self.dl = (days & 0xFF) as u8;
+        self.dh = (self.dh & !DH_DAY_MSB) | (days >> 8) as u8;
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        for reg in [self.s, self.m, self.h, self.dl, self.dh] {
+            out.extend_from_slice(&(reg as u32).to_le_bytes());
+        }
+    }
+
+    fn read(bytes: &[u8]) -> Registers {
+        let reg = |i: usize| bytes[i * 4];
+        Registers {
+            s: reg(0),
+            m: reg(1),
+            h: reg(2),
+            dl: reg(3),
+            dh: reg(4),
+        }
+    }
 }
 
-impl RTC {
-    pub fn init() -> RTC {
-        let path =
-            std::path::PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_string()))
-                .join(".boy/rtc_startup");
-
-        let startup = if path.exists() {
-            std::fs::read_to_string(&path)
-                .unwrap()
-                .trim()
-                .parse()
-                .unwrap()
-        } else {
-            let timestamp = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
+// Source of wall clock time, replaceable so tests and replays don't depend on the real time.
+pub trait Clock {
+    fn now_secs(&self) -> u64; // Unix timestamp
+}
 
-            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
-            std::fs::write(&path, timestamp.to_string()).unwrap();
+#[cfg(feature = "std")]
+pub struct SystemClock;
 
-            timestamp
-        };
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now_secs(&self) -> u64 {
+        // A system clock set before 1970 just reads as the epoch.
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+pub struct RTC {
+    clock: Box<dyn Clock>,
+    last_sync: u64,     // Unix timestamp the live registers were last advanced to
+    live: Registers,    // Running clock
+    latched: Registers, // Values visible to the CPU
+    latch: bool,
+}
 
+impl RTC {
+    pub fn init(clock: Box<dyn Clock>) -> RTC {
         Self {
-            startup,
-            rtc_s: 0,
-            rtc_m: 0,
-            rtc_h: 0,
-            rtc_dl: 0,
-            rtc_dh: 0,
+            last_sync: clock.now_secs(),
+            clock,
+            live: Registers::default(),
+            latched: Registers::default(),
             latch: false,
         }
     }
 
+    // Restores the clock from the footer of a battery save, then catches up
+    // with the time that passed while the emulator wasn't running.
+    // Footers of an unknown size are ignored.
+    pub fn restore(&mut self, data: &[u8]) {
+        let mut timestamp = [0; 8];
+        match data.len() {
+            RTC_SAVE_SIZE => timestamp.copy_from_slice(&data[40..48]),
+            RTC_SAVE_SIZE_LEGACY => timestamp[..4].copy_from_slice(&data[40..44]),
+            _ => return,
+        }
+        self.last_sync = u64::from_le_bytes(timestamp);
+        self.live = Registers::read(&data[0..20]);
+        self.latched = Registers::read(&data[20..40]);
+        self.latch = false;
+        self.sync();
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let now = self.clock.now_secs();
+        let mut live = self.live;
+        live.advance(now.saturating_sub(self.last_sync));
+
+        let mut out = Vec::with_capacity(RTC_SAVE_SIZE);
+        live.write(&mut out);
+        self.latched.write(&mut out);
+        out.extend_from_slice(&now.to_le_bytes());
+        out
+    }
+
+    fn sync(&mut self) {
+        let now = self.clock.now_secs();
+        self.live.advance(now.saturating_sub(self.last_sync));
+        self.last_sync = now;
+    }
+
     pub fn latch(&mut self, value: u8) {
         if value == 0x00 {
             self.latch = true;
@@ -63,53 +149,53 @@ impl RTC {
     }
 
     fn latch_values(&mut self) {
-        let counters = self.get_counters();
-
-        self.rtc_s = counters.seconds;
-        self.rtc_m = counters.minutes;
-        self.rtc_h = counters.hours;
-        self.rtc_dl = (counters.days & 0xFF) as u8;
-        self.rtc_dh = ((counters.days >> 8) & 0xFF) as u8;
+        self.sync();
+        self.latched = self.live;
     }
 
-    pub fn write_regisetr(&mut self, register: u8, value: u8) {
+    pub fn write_register(&mut self, register: u8, value: u8) {
+        // Bring the clock up to date first, so time elapsed before a halt is kept
+        // and time elapsed while halted is dropped.
+        self.sync();
         match register {
-            0x08 => self.rtc_s = value,
-            0x09 => self.rtc_m = value,
-            0x0A => self.rtc_h = value,
-            0x0B => self.rtc_dl = value,
-            0x0C => self.rtc_dh = value,
-            _ => unreachable!(),
+            0x08 => self.live.s = value & 0x3F,
+            0x09 => self.live.m = value & 0x3F,
+            0x0A => self.live.h = value & 0x1F,
+            0x0B => self.live.dl = value,
+            0x0C => self.live.dh = value & (DH_CARRY | DH_HALT | DH_DAY_MSB),
+            _ => (), // 0x0D-0x0F select nothing
         }
     }
 
     pub fn read_register(&self, register: u8) -> u8 {
         match register {
-            0x08 => self.rtc_s,
-            0x09 => self.rtc_m,
-            0x0A => self.rtc_h,
-            0x0B => self.rtc_dl,
-            0x0C => self.rtc_dh,
-            _ => unreachable!(),
+            0x08 => self.latched.s,
+            0x09 => self.latched.m,
+            0x0A => self.latched.h,
+            0x0B => self.latched.dl,
+            0x0C => self.latched.dh,
+            _ => 0xFF, // 0x0D-0x0F select nothing
         }
     }
+}
 
-    pub fn get_counters(&self) -> Counters {
-        let startup = std::time::UNIX_EPOCH + std::time::Duration::from_secs(self.startup);
-        let duration = std::time::SystemTime::now()
-            .duration_since(startup)
-            .unwrap();
-
-        let days = duration.as_secs() / 86400;
-        let hours = (duration.as_secs() % 86400) / 3600;
-        let minutes = (duration.as_secs() % 3600) / 60;
-        let seconds = duration.as_secs() % 60;
-
-        Counters {
-            seconds: seconds as u8,
-            minutes: minutes as u8,
-            hours: hours as u8,
-            days,
-        }
+impl Snapshot for RTC {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u64(self.last_sync);
+        let mut regs = Vec::with_capacity(40);
+        self.live.write(&mut regs);
+        self.latched.write(&mut regs);
+        w.bytes(&regs);
+        w.bool(self.latch);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.last_sync = r.u64()?;
+        let mut regs = [0; 40];
+        r.bytes(&mut regs)?;
+        self.live = Registers::read(&regs[0..20]);
+        self.latched = Registers::read(&regs[20..40]);
+        self.latch = r.bool()?;
+        Ok(())
     }
 }
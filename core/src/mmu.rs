@@ -1,56 +1,205 @@
+use alloc::{boxed::Box, vec::Vec};
+
 use crate::{
-    cart::Cart,
+    apu::{APU, AudioSink, NR10_ADDR, NR52_ADDR, WAVE_RAM_END, WAVE_RAM_START},
+    cart::{Cart, CartHeader},
     cpu::Cycles,
     gameboy::KeyStates,
-    interrupt::INTERRUPT_MASK,
+    interrupt::{INTERRUPT_MASK, Interrupt},
     joypad::{JOYP_ADDR, Joypad},
-    ppu::{DMA_ADDR, LCDC_ADDR, PPU, SCREEN_H, SCREEN_W, WX_ADDR},
+    ppu::{
+        BCPS_ADDR, DMA_ADDR, LCDC_ADDR, OCPD_ADDR, PPU, PpuEvents, SCREEN_H, SCREEN_W, VBK_ADDR,
+        WX_ADDR,
+    },
+    serial::{SB_ADDR, SC_ADDR, Serial},
+    state::{Snapshot, StateError, StateReader, StateWriter},
     timer::{DIV_ADDR, TAC_ADDR, Timer},
 };
 
 const IF_ADDR: u16 = 0xFF0F;
+const BOOT_ADDR: u16 = 0xFF50;
+const KEY1_ADDR: u16 = 0xFF4D;
+const HDMA1_ADDR: u16 = 0xFF51;
+const HDMA2_ADDR: u16 = 0xFF52;
+const HDMA3_ADDR: u16 = 0xFF53;
+const HDMA4_ADDR: u16 = 0xFF54;
+const HDMA5_ADDR: u16 = 0xFF55;
+const SVBK_ADDR: u16 = 0xFF70;
+const OAM_DMA_LEN: u16 = 0xA0; // One byte per M-cycle
 
 pub struct MMU {
-    cart: Cart,         // [0x0000 - 0x7FFF] - Cartridge ROM
-    wram: [u8; 0x2000], // [0xC000 - 0xDFFF] - Work RAM
+    cart: Cart,                // [0x0000 - 0x7FFF] - Cartridge ROM
+    boot_rom: Option<Vec<u8>>, // [0x0000 - 0x00FF] - Boot ROM, CGB ones also cover [0x0200 - 0x08FF]
+    boot_rom_mapped: bool,
+    wram: [u8; 0x8000], // [0xC000 - 0xDFFF] - Work RAM, 8 banks of 4 KiB on CGB
     hram: [u8; 0x7F],   // [0xFF80 - 0xFFFE] - High RAM
     if_: u8,            // [0xFF0F] - Interrupt Flag
+    dma: u8,            // [0xFF46] - OAM DMA source address & start
     ie: u8,             // [0xFFFF] - Interrupt Enable Register
+    oam_bug: bool,      // DMG CPU, 16-bit inc/dec on OAM addresses corrupts OAM
+    cgb: bool,          // Running a CGB game, enables the registers below
+    key1: u8,           // [0xFF4D] - KEY1: Prepare speed switch
+    hdma_src: u16,      // [0xFF51 - 0xFF52] - HDMA1/2: VRAM DMA source
+    hdma_dst: u16,      // [0xFF53 - 0xFF54] - HDMA3/4: VRAM DMA destination
+    hdma_len: u8,       // [0xFF55] - HDMA5: Blocks left minus one
+    hdma_active: bool,  // HBlank DMA running
+    svbk: u8,           // [0xFF70] - SVBK: WRAM bank
+    oam_dma: Option<OamDma>,
     ppu: PPU,
+    apu: APU,
     joypad: Joypad,
+    serial: Serial,
     timer: Timer,
+    cycles: u64, // T-cycles since power on
+    step: StepProgress,
+}
+
+// What the current CPU step has done so far, every bus access advances the
+// hardware by one M-cycle as it happens.
+#[derive(Default)]
+struct StepProgress {
+    cycles: Cycles,
+    frame_ready: bool,
+    events: PpuEvents,
+    last_events: PpuEvents, // Events of the previous, finished step
 }
 
 impl MMU {
-    pub fn new(cart: Cart) -> Self {
+    pub fn new(cart: Cart, cgb: bool) -> Self {
         MMU {
             cart,
-            wram: [0; 0x2000],
+            boot_rom: None,
+            boot_rom_mapped: false,
+            wram: [0; 0x8000],
             hram: [0; 0x7F],
             if_: 0xE0,
+            dma: 0xFF,
             ie: 0,
-            ppu: PPU::init(),
+            oam_bug: false,
+            cgb,
+            key1: 0,
+            hdma_src: 0,
+            hdma_dst: 0x8000,
+            hdma_len: 0x7F,
+            hdma_active: false,
+            svbk: 0,
+            oam_dma: None,
+            ppu: PPU::init(cgb),
+            apu: APU::new(),
             joypad: Joypad::new(),
+            serial: Serial::default(),
             timer: Timer::default(),
+            cycles: 0,
+            step: StepProgress::default(),
+        }
+    }
+
+    // Runs `boot` before the cartridge, starting from the power on state.
+    pub fn map_boot_rom(&mut self, boot: Vec<u8>) {
+        self.boot_rom = Some(boot);
+        self.reset();
+    }
+
+    // Only the DMG family has the bug, the CGB fixed it even in DMG mode.
+    pub fn set_oam_bug(&mut self, enabled: bool) {
+        self.oam_bug = enabled;
+    }
+
+    // Called by 16-bit INC/DEC with the register value they put on the address bus.
+    pub fn oam_bug_inc_dec(&mut self, addr: u16) {
+        if self.oam_bug && (0xFE00..=0xFEFF).contains(&addr) {
+            self.ppu.corrupt_oam_row();
+        }
+    }
+
+    pub fn has_boot_rom(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    // Power cycle everything but the cartridge, which only has its MBC registers reset.
+    pub fn reset(&mut self) {
+        self.cart.reset();
+        self.boot_rom_mapped = self.boot_rom.is_some();
+        self.wram.fill(0);
+        self.hram.fill(0);
+        self.if_ = 0xE0;
+        self.dma = 0xFF;
+        self.ie = 0;
+        self.key1 = 0;
+        self.hdma_src = 0;
+        self.hdma_dst = 0x8000;
+        self.hdma_len = 0x7F;
+        self.hdma_active = false;
+        self.svbk = 0;
+        self.oam_dma = None;
+        self.ppu = PPU::init(self.cgb);
+        if self.boot_rom_mapped {
+            // The LCD is off at power on, the boot ROM turns it on itself.
+            self.ppu.wb(LCDC_ADDR, 0);
         }
+        self.apu.reset();
+        self.joypad = Joypad::new();
+        self.serial = Serial::default();
+        self.timer = Timer::default();
+        self.cycles = 0;
+        self.step = StepProgress::default();
     }
 
+    // CPU bus read, takes one M-cycle.
     #[inline]
     pub fn rb(&mut self, addr: u16) -> u8 {
+        // While OAM DMA is running the CPU can only reach the I/O registers and HRAM.
+        let value = if self.oam_dma.is_some() && addr < 0xFF00 {
+            0xFF
+        } else {
+            self.read(addr)
+        };
+        self.tick(1);
+        value
+    }
+
+    // Debugger view of memory: no side effects, and neither OAM DMA nor the PPU modes block access.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.rb(addr),
+            _ => self.read(addr),
+        }
+    }
+
+    pub fn poke(&mut self, addr: u16, value: u8) {
         match addr {
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.wb(addr, value),
+            _ => self.write(addr, value),
+        }
+    }
+
+    #[inline]
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x08FF if self.boot_rom_mapped && self.in_boot_rom(addr) => self
+                .boot_rom
+                .as_ref()
+                .map_or(0xFF, |boot| boot[addr as usize]),
             0x0000..=0x7FFF => self.cart.rb(addr),
-            0x8000..=0x9FFF => self.ppu.rb(addr),  // VRAM
-            0xA000..=0xBFFF => self.cart.rb(addr), // ERAM
-            0xC000..=0xDFFF => self.wram[(addr - 0xC000) as usize],
-            0xE000..=0xFDFF => self.rb(addr - 0x2000), // Echo
-            0xFE00..=0xFE9F => self.ppu.rb(addr),      // OAM
-            0xFEA0..=0xFEFF => 0xFF,                   // Unusable
+            0x8000..=0x9FFF if !self.ppu.vram_accessible() => 0xFF, // VRAM locked in mode 3
+            0x8000..=0x9FFF => self.ppu.rb(addr),                   // VRAM
+            0xA000..=0xBFFF => self.cart.rb(addr),                  // ERAM
+            0xC000..=0xDFFF => self.wram[self.wram_index(addr)],
+            0xE000..=0xFDFF => self.read(addr - 0x2000), // Echo
+            0xFE00..=0xFE9F if !self.ppu.oam_accessible() => 0xFF, // OAM locked in modes 2 and 3
+            0xFE00..=0xFE9F => self.ppu.rb(addr),        // OAM
+            0xFEA0..=0xFEFF => 0xFF,                     // Unusable
             0xFF00..=0xFF7F => match addr {
                 JOYP_ADDR => self.joypad.rb(addr),          // Redirect to joypad
+                SB_ADDR..=SC_ADDR => self.serial.rb(addr),  // Redirect to serial
                 DIV_ADDR..=TAC_ADDR => self.timer.rb(addr), // Redirect to timer
-                DMA_ADDR => 0xFF,                           // Unsupported
-                LCDC_ADDR..=WX_ADDR => self.ppu.rb(addr),   // Redirect to PPU
+                NR10_ADDR..=NR52_ADDR => self.apu.rb(addr), // Redirect to APU
+                WAVE_RAM_START..=WAVE_RAM_END => self.apu.rb(addr),
+                DMA_ADDR => self.dma,
+                LCDC_ADDR..=WX_ADDR => self.ppu.rb(addr), // Redirect to PPU
                 IF_ADDR => self.if_ | 0xE0,
+                BOOT_ADDR => 0xFE | !self.boot_rom_mapped as u8,
+                KEY1_ADDR..=SVBK_ADDR if self.cgb => self.read_cgb(addr),
                 _ => 0xFF, // Unimplemented
             },
             0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
@@ -64,22 +213,42 @@ impl MMU {
         (hi << 8) | lo
     }
 
+    // CPU bus write, takes one M-cycle.
     #[inline]
     pub fn wb(&mut self, addr: u16, value: u8) {
+        if self.oam_dma.is_none() || addr >= 0xFF00 {
+            self.write(addr, value);
+        }
+        self.tick(1);
+    }
+
+    #[inline]
+    fn write(&mut self, addr: u16, value: u8) {
         match addr {
             0x0000..=0x7FFF => self.cart.wb(addr, value), // Cart / MBC
+            0x8000..=0x9FFF if !self.ppu.vram_accessible() => (), // VRAM locked in mode 3
             0x8000..=0x9FFF => self.ppu.wb(addr, value),  // VRAM
             0xA000..=0xBFFF => self.cart.wb(addr, value), // ERAM
-            0xC000..=0xDFFF => self.wram[(addr - 0xC000) as usize] = value,
-            0xE000..=0xFDFF => self.wb(addr - 0x2000, value),
-            0xFE00..=0xFE9F => self.ppu.wb(addr, value), // OAM
-            0xFEA0..=0xFEFF => (),                       // Unwriteable
+            0xC000..=0xDFFF => {
+                let index = self.wram_index(addr);
+                self.wram[index] = value
+            }
+            0xE000..=0xFDFF => self.write(addr - 0x2000, value),
+            0xFE00..=0xFE9F if !self.ppu.oam_accessible() => (), // OAM locked in modes 2 and 3
+            0xFE00..=0xFE9F => self.ppu.wb(addr, value),         // OAM
+            0xFEA0..=0xFEFF => (),                               // Unwriteable
             0xFF00..=0xFF7F => match addr {
                 JOYP_ADDR => self.joypad.wb(addr, value), // Redirect to joypad
+                SB_ADDR..=SC_ADDR => self.serial.wb(addr, value), // Redirect to serial
                 DIV_ADDR..=TAC_ADDR => self.timer.wb(addr, value), // Redirect to timer
-                DMA_ADDR => self.dma_transfer(value),     // OAM DMA source address & start
+                NR10_ADDR..=NR52_ADDR => self.apu.wb(addr, value), // Redirect to APU
+                WAVE_RAM_START..=WAVE_RAM_END => self.apu.wb(addr, value),
+                DMA_ADDR => self.start_oam_dma(value), // OAM DMA source address & start
                 LCDC_ADDR..=WX_ADDR => self.ppu.wb(addr, value), // Redirect to PPU
                 IF_ADDR => self.if_ = value & 0x1F,
+                // Only ever unmaps, the boot ROM can't be brought back until the next reset.
+                BOOT_ADDR if value & 0x01 != 0 => self.boot_rom_mapped = false,
+                KEY1_ADDR..=SVBK_ADDR if self.cgb => self.write_cgb(addr, value),
                 _ => (), // Unimplemented
             },
             0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize] = value,
@@ -87,15 +256,142 @@ impl MMU {
         }
     }
 
+    // The cartridge header at 0x0100 - 0x01FF always shows through.
+    fn in_boot_rom(&self, addr: u16) -> bool {
+        let len = self.boot_rom.as_ref().map_or(0, |boot| boot.len());
+        (addr as usize) < len && !(0x0100..=0x01FF).contains(&addr)
+    }
+
+    // 0xC000 - 0xCFFF is always bank 0, 0xD000 - 0xDFFF is the bank selected by SVBK, where 0 selects 1.
+    // A DMG never writes SVBK, so it sees banks 0 and 1 like a flat 8 KiB.
+    fn wram_index(&self, addr: u16) -> usize {
+        let offset = (addr & 0x0FFF) as usize;
+        match addr {
+            0xC000..=0xCFFF => offset,
+            _ => self.svbk.max(1) as usize * 0x1000 + offset,
+        }
+    }
+
+    // CGB registers, these read as 0xFF on a DMG.
+    fn read_cgb(&self, addr: u16) -> u8 {
+        match addr {
+            KEY1_ADDR => self.key1 | 0x7E,
+            VBK_ADDR => self.ppu.rb(addr),
+            BCPS_ADDR..=OCPD_ADDR => self.ppu.rb(addr),
+            HDMA5_ADDR if self.hdma_active => self.hdma_len,
+            HDMA5_ADDR => 0x80 | self.hdma_len,
+            SVBK_ADDR => self.svbk | 0xF8,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_cgb(&mut self, addr: u16, value: u8) {
+        match addr {
+            KEY1_ADDR => self.key1 = (self.key1 & 0x80) | (value & 0x01),
+            VBK_ADDR => self.ppu.wb(addr, value),
+            BCPS_ADDR..=OCPD_ADDR => self.ppu.wb(addr, value),
+            HDMA1_ADDR => self.hdma_src = (self.hdma_src & 0x00FF) | (value as u16) << 8,
+            HDMA2_ADDR => self.hdma_src = (self.hdma_src & 0xFF00) | (value & 0xF0) as u16,
+            HDMA3_ADDR => {
+                self.hdma_dst = 0x8000 | (self.hdma_dst & 0x00F0) | ((value & 0x1F) as u16) << 8
+            }
+            HDMA4_ADDR => self.hdma_dst = (self.hdma_dst & 0xFF00) | (value & 0xF0) as u16,
+            HDMA5_ADDR => self.start_vram_dma(value),
+            SVBK_ADDR => self.svbk = value & 0x07,
+            _ => (),
+        }
+    }
+
+    // HDMA5
+    // 7 - Mode: 0 = General purpose DMA, copies everything at once
+    //           1 = HBlank DMA, copies 16 bytes at the start of every HBlank
+    // 6 - 0 - Number of 16 byte blocks minus one
+    //
+    // Writing bit 7 = 0 while an HBlank DMA is running stops it instead.
+    fn start_vram_dma(&mut self, value: u8) {
+        if self.hdma_active && value & 0x80 == 0 {
+            self.hdma_active = false;
+            return;
+        }
+
+        self.hdma_len = value & 0x7F;
+        if value & 0x80 != 0 {
+            self.hdma_active = true;
+        } else {
+            // The CPU would be stalled for the duration, the copy is done instantly instead.
+            for _ in 0..=self.hdma_len {
+                self.copy_vram_dma_block();
+            }
+        }
+    }
+
+    fn copy_vram_dma_block(&mut self) {
+        for _ in 0..0x10 {
+            let value = self.read(self.hdma_src);
+            // Writes go to the VRAM bank selected by VBK, regardless of the PPU mode.
+            self.ppu.wb(self.hdma_dst, value);
+            self.hdma_src = self.hdma_src.wrapping_add(1);
+            self.hdma_dst = 0x8000 | (self.hdma_dst.wrapping_add(1) & 0x1FFF);
+        }
+
+        self.hdma_len = self.hdma_len.wrapping_sub(1) & 0x7F;
+        if self.hdma_len == 0x7F {
+            self.hdma_active = false;
+        }
+    }
+
+    pub fn cart_header(&self) -> &CartHeader {
+        &self.cart.header
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.cgb
+    }
+
+    // STOP switches speed instead of stopping once KEY1 bit 0 is set.
+    pub fn speed_switch_armed(&self) -> bool {
+        self.cgb && self.key1 & 0x01 != 0
+    }
+
+    pub fn switch_speed(&mut self) {
+        self.key1 = (self.key1 ^ 0x80) & 0x80;
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.key1 & 0x80 != 0
+    }
+
     pub fn ww(&mut self, addr: u16, value: u16) {
         self.wb(addr, (value & 0x00FF) as u8);
         self.wb(addr.wrapping_add(1), (value >> 8) as u8);
     }
 
-    pub fn get_fb(&self) -> [u8; SCREEN_W * SCREEN_H] {
+    pub fn dump_tiles(&self) -> Vec<u8> {
+        self.ppu.dump_tiles()
+    }
+
+    pub fn dump_tilemap(&self, high_map: bool) -> Vec<u8> {
+        self.ppu.dump_tilemap(high_map)
+    }
+
+    // Everything the PPU did during the last finished step.
+    pub fn ppu_events(&self) -> PpuEvents {
+        self.step.last_events
+    }
+
+    // Resets DIV without going through the bus, for STOP.
+    pub fn reset_div(&mut self) {
+        self.timer.wb(DIV_ADDR, 0);
+    }
+
+    pub fn get_fb(&self) -> [u16; SCREEN_W * SCREEN_H] {
         self.ppu.get_fb()
     }
 
+    pub fn frame_shades(&self) -> &[u8; SCREEN_W * SCREEN_H] {
+        self.ppu.shades()
+    }
+
     pub fn handle_joypad(&mut self, key_states: KeyStates) {
         let interrutps = self.joypad.tick(key_states);
 
@@ -104,11 +400,51 @@ impl MMU {
         }
     }
 
-    pub fn tick(&mut self, cycles: Cycles) -> bool {
+    pub fn take_sgb_packets(&mut self) -> Vec<[u8; 16]> {
+        self.joypad.take_sgb_packets()
+    }
+
+    pub fn joypad_pressed(&self) -> bool {
+        self.joypad.any_pressed()
+    }
+
+    // Runs the M-cycles of an instruction that weren't spent on the bus,
+    // returns true when a frame was completed during the step.
+    pub fn finish_step(&mut self, cycles: Cycles) -> bool {
+        self.tick(cycles.saturating_sub(self.step.cycles));
+
+        let step = core::mem::take(&mut self.step);
+        self.step.last_events = step.events;
+        step.frame_ready
+    }
+
+    fn tick(&mut self, cycles: Cycles) {
+        self.step.cycles += cycles;
+        self.cycles += to_tcycles(cycles) as u64;
+        self.tick_oam_dma(cycles);
+
         let mut interrupts = 0;
         interrupts |= self.timer.tick(to_tcycles(cycles));
+        interrupts |= self.serial.tick(to_tcycles(cycles));
+
+        // The PPU and APU keep running at normal speed, in double speed mode
+        // they only see half as many cycles as the CPU.
+        let lcd_cycles = if self.is_double_speed() {
+            to_tcycles(cycles) / 2
+        } else {
+            to_tcycles(cycles)
+        };
 
-        let (ppu_interrupts, frame_ready) = self.ppu.tick(to_tcycles(cycles));
+        self.apu.tick(lcd_cycles);
+
+        let (ppu_interrupts, frame_ready) = self.ppu.tick(lcd_cycles);
+
+        let events = self.ppu.events();
+        if events.hblank.is_some() && self.hdma_active {
+            self.copy_vram_dma_block();
+        }
+        self.step.events.vblank |= events.vblank;
+        self.step.events.hblank = events.hblank.or(self.step.events.hblank);
 
         interrupts |= ppu_interrupts;
 
@@ -116,13 +452,59 @@ impl MMU {
             self.request_interrupt(interrupts);
         }
 
-        frame_ready
+        if frame_ready {
+            self.apu.flush();
+            self.step.frame_ready = true;
+        }
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>, sample_rate: u32) {
+        self.apu.set_sink(sink, sample_rate);
+    }
+
+    pub fn clear_audio_sink(&mut self) {
+        self.apu.clear_sink();
+    }
+
+    pub fn set_audio_capture(&mut self, sink: Box<dyn AudioSink>, sample_rate: u32) {
+        self.apu.set_capture(sink, sample_rate);
+    }
+
+    pub fn clear_audio_capture(&mut self) {
+        self.apu.clear_capture();
     }
 
-    fn dma_transfer(&mut self, value: u8) {
-        for i in 0..160 {
-            let to_copy = self.rb(((value as u16) << 8) + i);
-            self.wb(0xFE00 + i, to_copy)
+    pub fn set_channel_enabled(&mut self, channel: u8, enabled: bool) {
+        self.apu.set_channel_enabled(channel, enabled);
+    }
+
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.serial.take_output()
+    }
+
+    fn start_oam_dma(&mut self, value: u8) {
+        self.dma = value;
+        self.oam_dma = Some(OamDma {
+            source: (value as u16) << 8,
+            progress: 0,
+        });
+    }
+
+    fn tick_oam_dma(&mut self, cycles: Cycles) {
+        for _ in 0..cycles {
+            let Some(dma) = self.oam_dma.as_mut() else {
+                return;
+            };
+
+            let (source, offset) = (dma.source, dma.progress);
+            dma.progress += 1;
+            if dma.progress == OAM_DMA_LEN {
+                self.oam_dma = None;
+            }
+
+            // DMA has its own path to the buses, the PPU's VRAM/OAM locks only apply to the CPU.
+            let to_copy = self.peek(source + offset);
+            self.ppu.wb(0xFE00 + offset, to_copy);
         }
     }
 
@@ -135,13 +517,25 @@ impl MMU {
         self.if_ |= bits;
     }
 
-    pub fn clear_interrupt(&mut self, bit: u8) {
-        self.if_ &= !bit;
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        self.if_ &= !interrupt.bit();
     }
 
     pub fn save(&self) -> Option<Vec<u8>> {
         self.cart.save()
     }
+
+    pub fn take_save_dirty(&mut self) -> bool {
+        self.cart.take_dirty()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn rom_checksum(&self) -> u64 {
+        self.cart.rom_checksum()
+    }
 }
 
 pub type TCycles = u32;
@@ -150,3 +544,70 @@ pub type TCycles = u32;
 fn to_tcycles(cycles: Cycles) -> TCycles {
     cycles as TCycles * 4
 }
+
+impl Snapshot for MMU {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.cart.save_state(w);
+        w.bool(self.boot_rom_mapped);
+        w.bytes(&self.wram);
+        w.bytes(&self.hram);
+        w.u8(self.if_);
+        w.u8(self.dma);
+        w.u8(self.ie);
+        w.u8(self.key1);
+        w.u16(self.hdma_src);
+        w.u16(self.hdma_dst);
+        w.u8(self.hdma_len);
+        w.bool(self.hdma_active);
+        w.u8(self.svbk);
+        w.bool(self.oam_dma.is_some());
+        if let Some(dma) = &self.oam_dma {
+            w.u16(dma.source);
+            w.u16(dma.progress);
+        }
+        w.u64(self.cycles);
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+        self.joypad.save_state(w);
+        self.serial.save_state(w);
+        self.timer.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.cart.load_state(r)?;
+        self.boot_rom_mapped = r.bool()? && self.boot_rom.is_some();
+        r.bytes(&mut self.wram)?;
+        r.bytes(&mut self.hram)?;
+        self.if_ = r.u8()?;
+        self.dma = r.u8()?;
+        self.ie = r.u8()?;
+        self.key1 = r.u8()? & 0x81;
+        self.hdma_src = r.u16()?;
+        self.hdma_dst = 0x8000 | (r.u16()? & 0x1FFF);
+        self.hdma_len = r.u8()? & 0x7F;
+        self.hdma_active = r.bool()?;
+        self.svbk = r.u8()? & 0x07;
+        self.oam_dma = if r.bool()? {
+            let source = r.u16()?;
+            let progress = r.u16()?;
+            if progress >= OAM_DMA_LEN {
+                return Err(StateError::Invalid);
+            }
+            Some(OamDma { source, progress })
+        } else {
+            None
+        };
+        self.cycles = r.u64()?;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        self.joypad.load_state(r)?;
+        self.serial.load_state(r)?;
+        self.timer.load_state(r)
+    }
+}
+
+// OAM DMA copies 160 bytes from 0xXX00 – 0xXX9F to OAM, one byte per M-cycle.
+struct OamDma {
+    source: u16,
+    progress: u16,
+}
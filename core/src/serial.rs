@@ -0,0 +1,82 @@
+use alloc::vec::Vec;
+
+use crate::{
+    interrupt::Interrupt,
+    mmu::TCycles,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub const SB_ADDR: u16 = 0xFF01;
+pub const SC_ADDR: u16 = 0xFF02;
+
+// Internal clock runs at 8192 Hz, 8 bits per transfer.
+const TRANSFER_CYCLES: TCycles = 512 * 8;
+
+#[derive(Default)]
+pub struct Serial {
+    sb: u8,                // [0xFF01] — SB: Serial transfer data
+    sc: u8, // [0xFF02] — SC: Serial transfer control [ 7 - - - - - - 0 ] 7: Transfer enable 0: Clock select
+    transfer_acc: TCycles, // Cycles spent on the ongoing transfer
+    output: Vec<u8>, // Bytes shifted out since the last take_output
+}
+
+impl Serial {
+    pub fn rb(&self, addr: u16) -> u8 {
+        match addr {
+            SB_ADDR => self.sb,
+            SC_ADDR => self.sc | 0x7E,
+            _ => panic!("Unexpected read at addr: 0x{addr:04X} on Serial."),
+        }
+    }
+
+    pub fn wb(&mut self, addr: u16, value: u8) {
+        match addr {
+            SB_ADDR => self.sb = value,
+            SC_ADDR => {
+                self.sc = value & 0x81;
+                self.transfer_acc = 0;
+            }
+            _ => panic!("Unexpected write at addr: 0x{addr:04X} on Serial."),
+        }
+    }
+
+    pub fn tick(&mut self, cycles: TCycles) -> u8 {
+        // Only transfers driven by the internal clock can progress, with no cable
+        // connected nothing ever clocks an external transfer.
+        if self.sc != 0x81 {
+            return 0;
+        }
+
+        self.transfer_acc += cycles;
+        if self.transfer_acc < TRANSFER_CYCLES {
+            return 0;
+        }
+
+        // Nothing on the other end, every bit shifted in reads 1.
+        self.output.push(self.sb);
+        self.sb = 0xFF;
+        self.sc &= !0x80;
+        self.transfer_acc = 0;
+
+        Interrupt::Serial.bit()
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.output)
+    }
+}
+
+impl Snapshot for Serial {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.sb);
+        w.u8(self.sc);
+        w.u32(self.transfer_acc);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.sb = r.u8()?;
+        self.sc = r.u8()? & 0x81;
+        self.transfer_acc = r.u32()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+// Save state format
+//
+// [ magic "BOYS" ] [ version: u16 ] [ rom checksum: u64 ] [ CPU ] [ MMU ]
+//
+// Every component writes its fields in declaration order, little endian.
+// Bump STATE_VERSION whenever the layout of any component changes.
+
+use alloc::vec::Vec;
+
+const STATE_MAGIC: [u8; 4] = *b"BOYS";
+pub const STATE_VERSION: u16 = 18;
+
+#[derive(Debug)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion { version: u16 },
+    RomMismatch,
+    Truncated,
+    Invalid,
+}
+
+impl core::fmt::Display for StateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion { version } => {
+                write!(
+                    f,
+                    "unsupported save state version: {version} (expected {STATE_VERSION})"
+                )
+            }
+            StateError::RomMismatch => write!(f, "save state was made with a different rom"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid => write!(f, "save state contains invalid data"),
+        }
+    }
+}
+
+impl core::error::Error for StateError {}
+
+pub trait Snapshot {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(rom_checksum: u64) -> Self {
+        let mut w = StateWriter::default();
+        w.bytes(&STATE_MAGIC);
+        w.u16(STATE_VERSION);
+        w.u64(rom_checksum);
+        w
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    // Fixed size data, the reader must know the length.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    // Variable size data, prefixed with its length.
+    pub fn vec(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8], rom_checksum: u64) -> Result<Self, StateError> {
+        let mut r = StateReader { data };
+
+        let mut magic = [0; 4];
+        r.bytes(&mut magic).map_err(|_| StateError::BadMagic)?;
+        if magic != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+
+        let version = r.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion { version });
+        }
+
+        if r.u64()? != rom_checksum {
+            return Err(StateError::RomMismatch);
+        }
+
+        Ok(r)
+    }
+
+    // Fails if anything was left unread, which means the layouts don't match.
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::Invalid)
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut out = [0; N];
+        self.bytes(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn bytes(&mut self, out: &mut [u8]) -> Result<(), StateError> {
+        out.copy_from_slice(self.take(out.len())?);
+        Ok(())
+    }
+
+    // Reads data written with `StateWriter::vec`, its length must match `out`.
+    pub fn vec(&mut self, out: &mut [u8]) -> Result<(), StateError> {
+        if self.u32()? as usize != out.len() {
+            return Err(StateError::Invalid);
+        }
+        self.bytes(out)
+    }
+}
+
+// FNV-1a, only used to recognize the ROM a state belongs to.
+pub fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
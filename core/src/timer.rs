@@ -1,4 +1,8 @@
-use crate::{interrupt::Interrupt, mmu::TCycles};
+use crate::{
+    interrupt::Interrupt,
+    mmu::TCycles,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 pub const DIV_ADDR: u16 = 0xFF04;
 pub const TIMA_ADDR: u16 = 0xFF05;
@@ -7,11 +11,11 @@ pub const TAC_ADDR: u16 = 0xFF07;
 
 #[derive(Default)]
 pub struct Timer {
-    div: u16,      // [0xFF04] — DIV: Divider register
-    tima: u8,      // [0xFF05] — TIMA: Timer counter
-    tma: u8,       // [0xFF06] — TMA: Timer modulo
-    tac: u8,       // [0xFF07] — TAC: Timer control [ - - - - - 2 1 0 ] 2: Enable 1 0: Clock select
-    tima_acc: u32, // TIMA accumulator
+    div: u16,        // [0xFF04] — DIV: Divider register
+    tima: u8,        // [0xFF05] — TIMA: Timer counter
+    tma: u8,         // [0xFF06] — TMA: Timer modulo
+    tac: u8, // [0xFF07] — TAC: Timer control [ - - - - - 2 1 0 ] 2: Enable 1 0: Clock select
+    reloading: bool, // TIMA overflowed last M-cycle, TMA gets loaded this one
 }
 
 impl Timer {
@@ -20,57 +24,105 @@ impl Timer {
             DIV_ADDR => (self.div >> 8) as u8,
             TIMA_ADDR => self.tima,
             TMA_ADDR => self.tma,
-            TAC_ADDR => self.tac,
+            TAC_ADDR => self.tac | 0xF8, // Upper bits are unused and read 1
             _ => panic!("Unexpected read at addr: 0x{addr:04X} on Timer."),
         }
     }
 
     pub fn wb(&mut self, addr: u16, value: u8) {
         match addr {
-            DIV_ADDR => self.div = 0,
-            TIMA_ADDR => self.tima = value,
+            DIV_ADDR => self.set_div(0),
+            TIMA_ADDR => {
+                // Writing TIMA while it's reloading cancels the reload and the interrupt.
+                self.tima = value;
+                self.reloading = false;
+            }
             TMA_ADDR => self.tma = value,
-            TAC_ADDR => self.tac = value & 0x07,
+            TAC_ADDR => {
+                let before = self.input();
+                self.tac = value & 0x07;
+                self.detect_falling_edge(before);
+            }
             _ => panic!("Unexpected write at addr: 0x{addr:04X} on Timer."),
         }
     }
 
     pub fn tick(&mut self, cycles: TCycles) -> u8 {
-        self.div = self.div.wrapping_add(cycles as u16);
+        let mut interrupt = 0;
+        for _ in 0..cycles / 4 {
+            interrupt |= self.step();
+        }
+        interrupt
+    }
+
+    // Advances the timer by one M-cycle.
+    fn step(&mut self) -> u8 {
+        let mut interrupt = 0;
+
+        // On overflow TIMA reads 0 for one M-cycle before TMA is loaded,
+        // so a TMA write during that cycle still makes it into TIMA.
+        if self.reloading {
+            self.reloading = false;
+            self.tima = self.tma;
+            interrupt = Interrupt::Timer.bit();
+        }
+
+        self.set_div(self.div.wrapping_add(4));
+
+        interrupt
+    }
 
+    // TIMA is clocked by the falling edge of one DIV bit, ANDed with the enable bit.
+    // So resetting DIV or changing TAC can increment TIMA as well.
+    // ref: [https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html]
+    fn input(&self) -> bool {
         // Timer enabled
         if (self.tac & 0x04) == 0 {
-            return 0;
+            return false;
         }
 
         // Clock selection
-        let period = match self.tac & 0x03 {
-            0x00 => 1024,
-            0x01 => 16,
-            0x02 => 64,
-            0x03 => 256,
+        let bit = match self.tac & 0x03 {
+            0x00 => 9, // 1024 T-cycles
+            0x01 => 3, // 16 T-cycles
+            0x02 => 5, // 64 T-cycles
+            0x03 => 7, // 256 T-cycles
             _ => unreachable!(),
         };
 
-        self.tima_acc = self.tima_acc.wrapping_add(cycles);
+        self.div & (1 << bit) != 0
+    }
 
-        let mut overflowed = false;
-        while self.tima_acc >= period {
-            self.tima_acc -= period;
+    fn set_div(&mut self, value: u16) {
+        let before = self.input();
+        self.div = value;
+        self.detect_falling_edge(before);
+    }
 
-            let (new, did_overflow) = self.tima.overflowing_add(1);
-            if did_overflow {
-                self.tima = self.tma;
-                overflowed = true;
-            } else {
-                self.tima = new;
-            }
+    fn detect_falling_edge(&mut self, before: bool) {
+        if before && !self.input() {
+            let (new, overflowed) = self.tima.overflowing_add(1);
+            self.tima = new;
+            self.reloading |= overflowed;
         }
+    }
+}
 
-        if overflowed {
-            Interrupt::Timer.bit()
-        } else {
-            0
-        }
+impl Snapshot for Timer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.div);
+        w.u8(self.tima);
+        w.u8(self.tma);
+        w.u8(self.tac);
+        w.bool(self.reloading);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.div = r.u16()?;
+        self.tima = r.u8()?;
+        self.tma = r.u8()?;
+        self.tac = r.u8()? & 0x07;
+        self.reloading = r.bool()?;
+        Ok(())
     }
 }
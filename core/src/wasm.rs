@@ -0,0 +1,65 @@
+use alloc::{boxed::Box, string::ToString, vec::Vec};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    cart::{Cart, Clock},
+    gameboy::{Buttons, GameBoy},
+    ppu::{SCREEN_H, SCREEN_W},
+};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date)]
+    fn now() -> f64;
+}
+
+// The browser has no system clock for std to read, the RTC asks JS instead.
+struct DateClock;
+
+impl Clock for DateClock {
+    fn now_secs(&self) -> u64 {
+        (now() / 1000.0) as u64
+    }
+}
+
+#[wasm_bindgen]
+pub struct WebGameBoy {
+    gameboy: GameBoy,
+}
+
+#[wasm_bindgen]
+impl WebGameBoy {
+    // `save` is the battery save returned by `save()` in an earlier session, if any.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8], save: Option<Vec<u8>>) -> Result<WebGameBoy, JsError> {
+        let cart = Cart::from_bytes_with_clock(rom.to_vec(), save, Box::new(DateClock), None)
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(WebGameBoy {
+            gameboy: GameBoy::new(cart),
+        })
+    }
+
+    // Runs one frame with `keys` held and returns it as RGBA, ready for an ImageData.
+    // Keys: A=1, B=2, Select=4, Start=8, Right=16, Left=32, Up=64, Down=128.
+    pub fn frame(&mut self, keys: u8) -> Result<Vec<u8>, JsError> {
+        self.gameboy
+            .run_frame(Buttons(keys))
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(self.gameboy.frame_rgba())
+    }
+
+    pub fn save(&self) -> Option<Vec<u8>> {
+        self.gameboy.save()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        SCREEN_W
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        SCREEN_H
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::apu::AudioSink;
+
+pub const WAV_SAMPLE_RATE: u32 = 44_100;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const HEADER_LEN: u32 = 44;
+
+// Writes the mixed audio as 16-bit stereo PCM. The RIFF sizes are patched after every chunk,
+// so the file stays playable even if the emulator never gets to stop the capture.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut writer = WavWriter {
+            file: BufWriter::new(File::create(path)?),
+            data_len: 0,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    // ref: [http://soundfile.sapp.org/doc/WaveFormat/]
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(b"RIFF")?;
+        self.file
+            .write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.file.write_all(b"WAVE")?;
+
+        self.file.write_all(b"fmt ")?;
+        self.file.write_all(&16u32.to_le_bytes())?; // fmt chunk length
+        self.file.write_all(&1u16.to_le_bytes())?; // PCM
+        self.file.write_all(&CHANNELS.to_le_bytes())?;
+        self.file.write_all(&WAV_SAMPLE_RATE.to_le_bytes())?;
+        self.file
+            .write_all(&(WAV_SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+        self.file.write_all(&block_align.to_le_bytes())?;
+        self.file.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        self.file.write_all(b"data")?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&pcm.to_le_bytes())?;
+        }
+        self.data_len += (samples.len() * 2) as u32;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn push_samples(&mut self, samples: &[f32]) {
+        // A failing disk only loses the capture, emulation goes on.
+        let _ = self.write_samples(samples);
+    }
+}
@@ -0,0 +1,169 @@
+#![cfg(feature = "std")]
+
+use core::{cart::Cart, cpu::CPU, gameboy::Model, mmu::MMU};
+
+// The proptest! macros expand to `::core` paths, which this crate's name shadows, so the
+// runner is driven directly.
+use proptest::{
+    prelude::*,
+    test_runner::{TestCaseError, TestRunner},
+};
+
+const CODE: u16 = 0xC000;
+
+const Z: u8 = 1 << 7;
+const N: u8 = 1 << 6;
+const H: u8 = 1 << 5;
+const C: u8 = 1 << 4;
+
+// Runs `code` from WRAM one instruction at a time, starting from A/F/B/SP.
+fn run(code: &[u8], steps: usize, a: u8, f: u8, b: u8, sp: u16) -> CPU {
+    let mut mmu = MMU::new(Cart::from_bytes(vec![0; 0x8000], None).unwrap(), false);
+    for (i, &byte) in code.iter().enumerate() {
+        mmu.poke(CODE + i as u16, byte);
+    }
+
+    let mut cpu = CPU::init(Model::DMG);
+    cpu.r.set_af(((a as u16) << 8) | f as u16);
+    cpu.r.b = b;
+    cpu.r.sp = sp;
+    cpu.r.pc = CODE;
+    for _ in 0..steps {
+        cpu.step(&mut mmu).unwrap();
+    }
+    cpu
+}
+
+fn flags(z: bool, n: bool, h: bool, c: bool) -> u8 {
+    (z as u8 * Z) | (n as u8 * N) | (h as u8 * H) | (c as u8 * C)
+}
+
+fn bcd(v: u8) -> u8 {
+    ((v / 10) << 4) | (v % 10)
+}
+
+// Shrinks and reports the smallest failing input.
+fn check<S: Strategy>(strategy: S, test: impl Fn(S::Value))
+where
+    S::Value: std::fmt::Debug,
+{
+    let result = TestRunner::default().run(&strategy, |value| {
+        test(value);
+        Ok::<(), TestCaseError>(())
+    });
+    if let Err(err) = result {
+        panic!("{err}");
+    }
+}
+
+// ADD A,B / ADC A,B, with the half carry read back from bit 4 of a ^ b ^ result.
+#[test]
+fn add() {
+    check(
+        (any::<u8>(), any::<u8>(), any::<bool>(), any::<bool>()),
+        |(a, b, carry, with_carry)| {
+            let op = if with_carry { 0x88 } else { 0x80 };
+            let cpu = run(&[op], 1, a, carry as u8 * C, b, 0xFFFE);
+
+            let cin = (with_carry && carry) as u16;
+            let sum = a as u16 + b as u16 + cin;
+            let res = sum as u8;
+            assert_eq!(cpu.r.a, res);
+            assert_eq!(
+                cpu.r.f(),
+                flags(res == 0, false, (a ^ b ^ res) & 0x10 != 0, sum > 0xFF)
+            );
+        },
+    );
+}
+
+// SUB B / SBC A,B, borrows computed on signed wide values.
+#[test]
+fn sub() {
+    check(
+        (any::<u8>(), any::<u8>(), any::<bool>(), any::<bool>()),
+        |(a, b, carry, with_carry)| {
+            let op = if with_carry { 0x98 } else { 0x90 };
+            let cpu = run(&[op], 1, a, carry as u8 * C, b, 0xFFFE);
+
+            let cin = (with_carry && carry) as i16;
+            let diff = a as i16 - b as i16 - cin;
+            let res = diff as u8;
+            let half = (a & 0x0F) as i16 - (b & 0x0F) as i16 - cin;
+            assert_eq!(cpu.r.a, res);
+            assert_eq!(cpu.r.f(), flags(res == 0, true, half < 0, diff < 0));
+        },
+    );
+}
+
+// INC B / DEC B leave the carry alone.
+#[test]
+fn inc_dec() {
+    check((any::<u8>(), 0u8..16, any::<bool>()), |(v, f, dec)| {
+        let f = f << 4;
+        let op = if dec { 0x05 } else { 0x04 };
+        let cpu = run(&[op], 1, 0, f, v, 0xFFFE);
+
+        let (res, half) = if dec {
+            (v.wrapping_sub(1), v & 0x0F == 0)
+        } else {
+            (v.wrapping_add(1), v & 0x0F == 0x0F)
+        };
+        assert_eq!(cpu.r.b, res);
+        assert_eq!(cpu.r.f(), flags(res == 0, dec, half, f & C != 0));
+    });
+}
+
+// ADD SP,r8 takes H and C from an unsigned add on the low byte, Z and N are cleared.
+#[test]
+fn add_sp() {
+    check((any::<u16>(), any::<i8>(), 0u8..16), |(sp, offset, f)| {
+        let cpu = run(&[0xE8, offset as u8], 1, 0, f << 4, 0, sp);
+
+        let low = offset as u8;
+        assert_eq!(cpu.r.sp, sp.wrapping_add(offset as i16 as u16));
+        assert_eq!(
+            cpu.r.f(),
+            flags(
+                false,
+                false,
+                (sp & 0x0F) + (low as u16 & 0x0F) > 0x0F,
+                (sp & 0xFF) + low as u16 > 0xFF,
+            )
+        );
+    });
+}
+
+// Adding two BCD numbers then DAA gives the decimal sum, carry out of the hundreds.
+#[test]
+fn daa_after_add() {
+    check(
+        (0u8..100, 0u8..100, any::<bool>(), any::<bool>()),
+        |(x, y, carry, with_carry)| {
+            let op = if with_carry { 0x88 } else { 0x80 };
+            let cpu = run(&[op, 0x27], 2, bcd(x), carry as u8 * C, bcd(y), 0xFFFE);
+
+            let sum = x + y + (with_carry && carry) as u8;
+            let res = bcd(sum % 100);
+            assert_eq!(cpu.r.a, res);
+            assert_eq!(cpu.r.f(), flags(res == 0, false, false, sum >= 100));
+        },
+    );
+}
+
+// Subtracting two BCD numbers then DAA gives the decimal difference, carry on borrow.
+#[test]
+fn daa_after_sub() {
+    check(
+        (0u8..100, 0u8..100, any::<bool>(), any::<bool>()),
+        |(x, y, carry, with_carry)| {
+            let op = if with_carry { 0x98 } else { 0x90 };
+            let cpu = run(&[op, 0x27], 2, bcd(x), carry as u8 * C, bcd(y), 0xFFFE);
+
+            let diff = x as i16 - y as i16 - (with_carry && carry) as i16;
+            let res = bcd(diff.rem_euclid(100) as u8);
+            assert_eq!(cpu.r.a, res);
+            assert_eq!(cpu.r.f(), flags(res == 0, true, false, diff < 0));
+        },
+    );
+}
@@ -0,0 +1,46 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use core::gameboy::Buttons;
+
+// About a minute of emulated time, the slowest sub-test needs well under that.
+const FRAME_BUDGET: u32 = 60 * 60;
+
+const CPU_INSTRS: [&str; 11] = [
+    "01-special.gb",
+    "02-interrupts.gb",
+    "03-op sp,hl.gb",
+    "04-op r,imm.gb",
+    "05-op rp.gb",
+    "06-ld r,r.gb",
+    "07-jr,jp,call,ret,rst.gb",
+    "08-misc instrs.gb",
+    "09-op r,r.gb",
+    "10-bit ops.gb",
+    "11-op a,(hl).gb",
+];
+
+// Blargg's ROMs print their results over the serial port and end with "Passed" or "Failed".
+fn run(rel: &str) {
+    let mut gameboy = common::load(rel);
+
+    let mut output = String::new();
+    for _ in 0..FRAME_BUDGET {
+        gameboy.run_frame(Buttons::NONE).unwrap();
+        output.push_str(&String::from_utf8_lossy(&gameboy.take_serial_output()));
+        if output.contains("Passed") || output.contains("Failed") {
+            break;
+        }
+    }
+
+    assert!(output.contains("Passed"), "{rel}:\n{output}");
+}
+
+#[test]
+#[ignore = "needs Blargg's ROMs in tests/roms, see tests/roms/README.md"]
+fn cpu_instrs() {
+    for rom in CPU_INSTRS {
+        run(&format!("blargg/cpu_instrs/{rom}"));
+    }
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "std")]
+
+use core::{cart::Cart, cpu::CPU, gameboy::Model, mmu::MMU};
+
+const CODE: u16 = 0xC000;
+
+// M-cycles taken by CB opcodes `first..=last`, with the (HL) operand and with a register one.
+const CB_CYCLES: [(u8, u8, u8, u8); 4] = [
+    (0x00, 0x3F, 4, 2), // Rotates, shifts and SWAP
+    (0x40, 0x7F, 3, 2), // BIT only reads (HL)
+    (0x80, 0xBF, 4, 2), // RES
+    (0xC0, 0xFF, 4, 2), // SET
+];
+
+fn cb_cycles(op: u8) -> u8 {
+    let mut mmu = MMU::new(Cart::from_bytes(vec![0; 0x8000], None).unwrap(), false);
+    mmu.poke(CODE, 0xCB);
+    mmu.poke(CODE + 1, op);
+
+    let mut cpu = CPU::init(Model::DMG);
+    cpu.r.pc = CODE;
+    cpu.r.h = 0xD0; // (HL) in WRAM
+    cpu.r.l = 0x00;
+    cpu.step(&mut mmu).unwrap()
+}
+
+#[test]
+fn cb_opcode_cycles() {
+    for (first, last, hl_cycles, reg_cycles) in CB_CYCLES {
+        for op in first..=last {
+            let expected = if op & 0x07 == 6 {
+                hl_cycles
+            } else {
+                reg_cycles
+            };
+            assert_eq!(cb_cycles(op), expected, "CB {op:02X}");
+        }
+    }
+}
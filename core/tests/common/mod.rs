@@ -0,0 +1,20 @@
+use std::path::{Path, PathBuf};
+
+use core::{cart::Cart, gameboy::GameBoy};
+
+// Test ROMs aren't redistributable alongside the code, see tests/roms/README.md.
+pub fn rom_path(rel: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/roms")
+        .join(rel)
+}
+
+// Panics when the ROM isn't there, a suite with missing ROMs must not pass.
+pub fn load(rel: &str) -> GameBoy {
+    let path = rom_path(rel);
+    if !path.exists() {
+        panic!("{rel}: ROM not found, see tests/roms/README.md");
+    }
+    let cart = Cart::from_path(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    GameBoy::new(cart)
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "std")]
+
+use core::{cart::Cart, cpu::CPU, gameboy::Model, mmu::MMU};
+
+const CODE: u16 = 0xC000;
+const IF_ADDR: u16 = 0xFF0F;
+const IE_ADDR: u16 = 0xFFFF;
+
+const VBLANK: u8 = 1 << 0;
+const TIMER: u8 = 1 << 2;
+
+// CPU about to run `code` from WRAM, with `pending` requested and enabled in IE.
+fn setup(code: &[u8], pending: u8) -> (CPU, MMU) {
+    let mut mmu = MMU::new(Cart::from_bytes(vec![0; 0x8000], None).unwrap(), false);
+    for (i, &byte) in code.iter().enumerate() {
+        mmu.poke(CODE + i as u16, byte);
+    }
+    mmu.poke(IE_ADDR, 0x1F);
+    mmu.request_interrupt(pending);
+
+    let mut cpu = CPU::init(Model::DMG);
+    cpu.r.pc = CODE;
+    (cpu, mmu)
+}
+
+#[test]
+fn ei_then_di_services_nothing() {
+    let (mut cpu, mut mmu) = setup(&[0xFB, 0xF3, 0x00, 0x00], VBLANK); // EI; DI; NOP; NOP
+    for _ in 0..4 {
+        cpu.step(&mut mmu).unwrap();
+    }
+
+    assert_eq!(cpu.r.pc, CODE + 4);
+    assert_eq!(mmu.peek(IF_ADDR) & VBLANK, VBLANK);
+}
+
+#[test]
+fn vblank_wins_when_everything_is_pending() {
+    let (mut cpu, mut mmu) = setup(&[0x00], 0x1F);
+    cpu.ime = true;
+    cpu.step(&mut mmu).unwrap();
+
+    assert_eq!(cpu.r.pc, 0x40);
+}
+
+#[test]
+fn servicing_vblank_leaves_timer_pending() {
+    let (mut cpu, mut mmu) = setup(&[0x00], VBLANK | TIMER);
+    cpu.ime = true;
+    cpu.step(&mut mmu).unwrap();
+
+    assert_eq!(cpu.r.pc, 0x40);
+    assert_eq!(mmu.peek(IF_ADDR) & 0x1F, TIMER);
+}
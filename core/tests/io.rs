@@ -0,0 +1,26 @@
+#![cfg(feature = "std")]
+
+use std::ops::RangeInclusive;
+
+use core::{cart::Cart, mmu::MMU};
+
+// IO addresses without a register behind them on DMG.
+const UNMAPPED: [RangeInclusive<u16>; 7] = [
+    0xFF03..=0xFF03,
+    0xFF08..=0xFF0E,
+    0xFF15..=0xFF15,
+    0xFF1F..=0xFF1F,
+    0xFF27..=0xFF2F,
+    0xFF4C..=0xFF4F,
+    0xFF51..=0xFF7F,
+];
+
+#[test]
+fn unmapped_io_reads_ff() {
+    let mut mmu = MMU::new(Cart::from_bytes(vec![0; 0x8000], None).unwrap(), false);
+
+    for addr in UNMAPPED.into_iter().flatten() {
+        mmu.poke(addr, 0x00);
+        assert_eq!(mmu.peek(addr), 0xFF, "{addr:#06X}");
+    }
+}
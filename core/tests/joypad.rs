@@ -0,0 +1,70 @@
+#![cfg(feature = "std")]
+
+use core::{
+    cart::Cart,
+    gameboy::{Buttons, GameBoy, KeyStates},
+    mmu::MMU,
+};
+
+const JOYP: u16 = 0xFF00;
+const IF_ADDR: u16 = 0xFF0F;
+const JOYPAD: u8 = 1 << 4;
+
+// Pulses one SGB packet bit on P14/P15, then releases both lines.
+fn send_bit(gameboy: &mut GameBoy, bit: bool) {
+    gameboy.write_byte(JOYP, if bit { 0x10 } else { 0x20 });
+    gameboy.write_byte(JOYP, 0x30);
+}
+
+fn mmu() -> MMU {
+    MMU::new(Cart::from_bytes(vec![0; 0x8000], None).unwrap(), false)
+}
+
+#[test]
+fn selected_group_drives_low_nibble() {
+    let mut mmu = mmu();
+    mmu.handle_joypad(KeyStates::from(Buttons(Buttons::A.0 | Buttons::UP.0)));
+
+    // (select bits written, low nibble read back)
+    for (select, nibble) in [(0x10, 0x0E), (0x20, 0x0B), (0x00, 0x0A), (0x30, 0x0F)] {
+        mmu.poke(JOYP, select);
+        assert_eq!(mmu.peek(JOYP) & 0x0F, nibble, "select {select:#04x}");
+    }
+}
+
+#[test]
+fn press_outside_selected_group_raises_nothing() {
+    let mut mmu = mmu();
+    mmu.poke(JOYP, 0x10); // Buttons
+    mmu.handle_joypad(KeyStates::from(Buttons::UP));
+    assert_eq!(mmu.peek(IF_ADDR) & JOYPAD, 0);
+
+    mmu.poke(JOYP, 0x20); // D-pad
+    mmu.handle_joypad(KeyStates::from(Buttons::NONE));
+    mmu.handle_joypad(KeyStates::from(Buttons::UP));
+    assert_eq!(mmu.peek(IF_ADDR) & JOYPAD, JOYPAD);
+}
+
+#[test]
+fn half_sent_sgb_packet_survives_save_state() {
+    let packet: [u8; 16] = std::array::from_fn(|i| i as u8 * 17);
+    let bits = (0..128).map(|i| packet[i / 8] & (1 << (i % 8)) != 0);
+    let rom = vec![0; 0x8000];
+
+    let mut gameboy = GameBoy::new(Cart::from_bytes(rom.clone(), None).unwrap());
+    gameboy.write_byte(JOYP, 0x00); // Reset
+    gameboy.write_byte(JOYP, 0x30);
+    for bit in bits.clone().take(64) {
+        send_bit(&mut gameboy, bit);
+    }
+    let state = gameboy.save_state();
+
+    let mut gameboy = GameBoy::new(Cart::from_bytes(rom, None).unwrap());
+    gameboy.load_state(&state).unwrap();
+    for bit in bits.skip(64) {
+        send_bit(&mut gameboy, bit);
+    }
+    send_bit(&mut gameboy, false); // Stop bit
+
+    assert_eq!(gameboy.take_sgb_packets(), vec![packet]);
+}
@@ -0,0 +1,180 @@
+#![cfg(feature = "std")]
+
+use std::{cell::Cell, rc::Rc};
+
+use core::cart::{Cart, CartError, Clock};
+
+// Every ROM bank holds its own number at BANK_MARK.
+const BANK_MARK: u16 = 0x3000;
+
+// `cartridge_type` and `ram_id` as found at 0x147 and 0x149 of the header.
+fn rom(cartridge_type: u8, rom_banks: usize, ram_id: u8) -> Vec<u8> {
+    let mut rom = vec![0u8; rom_banks * 0x4000];
+    for bank in 0..rom_banks {
+        rom[bank * 0x4000 + BANK_MARK as usize] = bank as u8;
+    }
+    rom[0x147] = cartridge_type;
+    rom[0x148] = (rom_banks / 2).trailing_zeros() as u8;
+    rom[0x149] = ram_id;
+    rom
+}
+
+fn cart(cartridge_type: u8, rom_banks: usize, ram_id: u8) -> Cart {
+    Cart::from_bytes(rom(cartridge_type, rom_banks, ram_id), None).unwrap()
+}
+
+// A clock that only moves when the test says so.
+#[derive(Clone, Default)]
+struct FixedClock(Rc<Cell<u64>>);
+
+impl FixedClock {
+    fn advance(&self, secs: u64) {
+        self.0.set(self.0.get() + secs);
+    }
+}
+
+impl Clock for FixedClock {
+    fn now_secs(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+#[test]
+fn mbc5_ram_bank_wraps_to_ram_size() {
+    let mut cart = cart(0x1A, 2, 0x02); // MBC5+RAM, 8 KiB
+    cart.wb(0x0000, 0x0A);
+    cart.wb(0x4000, 0x03);
+    cart.wb(0xA000, 0x42);
+
+    cart.wb(0x4000, 0x00);
+    assert_eq!(cart.rb(0xA000), 0x42);
+}
+
+#[test]
+fn mbc1_bank_1_stays_out_of_bank_0_area() {
+    let mut cart = cart(0x01, 4, 0x00); // MBC1, 64 KiB
+    cart.wb(0x2000, 0x01);
+
+    assert_eq!(cart.rb(BANK_MARK), 0);
+    assert_eq!(cart.rb(0x4000 + BANK_MARK), 1);
+}
+
+#[test]
+fn mbc1_2kib_ram_mirrors() {
+    let mut cart = cart(0x02, 2, 0x01); // MBC1+RAM, 2 KiB
+    cart.wb(0x0000, 0x0A);
+    cart.wb(0xA000, 0x42);
+
+    assert_eq!(cart.rb(0xA800), 0x42);
+}
+
+#[test]
+fn rom_bank_wraps_to_rom_size() {
+    for cartridge_type in [0x01, 0x11, 0x19] {
+        let mut cart = cart(cartridge_type, 4, 0x00); // 64 KiB
+        cart.wb(0x2000, 0x07);
+
+        assert_eq!(cart.rb(0x4000 + BANK_MARK), 3, "type {cartridge_type:#04x}");
+    }
+}
+
+#[test]
+fn mbc3_latched_rtc_stays_frozen() {
+    let clock = FixedClock::default();
+    let rom = rom(0x10, 2, 0x02); // MBC3+TIMER+RAM+BATTERY
+    let mut cart = Cart::from_bytes_with_clock(rom, None, Box::new(clock.clone()), None).unwrap();
+    cart.wb(0x0000, 0x0A);
+    let read_rtc = |cart: &mut Cart, register: u8| {
+        cart.wb(0x4000, register);
+        cart.rb(0xA000)
+    };
+
+    clock.advance(5);
+    cart.wb(0x6000, 0x00);
+    cart.wb(0x6000, 0x01);
+    assert_eq!(read_rtc(&mut cart, 0x08), 5);
+
+    // Time keeps running, the latched registers don't, not even on a 1 without the 0 first.
+    clock.advance(70);
+    cart.wb(0x6000, 0x01);
+    assert_eq!(read_rtc(&mut cart, 0x08), 5);
+    assert_eq!(read_rtc(&mut cart, 0x09), 0);
+
+    cart.wb(0x6000, 0x00);
+    cart.wb(0x6000, 0x01);
+    assert_eq!(read_rtc(&mut cart, 0x08), 15);
+    assert_eq!(read_rtc(&mut cart, 0x09), 1);
+}
+
+#[test]
+fn ram_size_override_beats_header() {
+    // MBC5+RAM declaring no RAM, overridden to 4 banks.
+    let mut cart = Cart::from_bytes_with_clock(
+        rom(0x1A, 2, 0x00),
+        None,
+        Box::new(FixedClock::default()),
+        Some(32 * 1024),
+    )
+    .unwrap();
+    cart.wb(0x0000, 0x0A);
+    cart.wb(0x4000, 0x03);
+    cart.wb(0xA000, 0x42);
+
+    assert_eq!(cart.rb(0xA000), 0x42);
+    cart.wb(0x4000, 0x00);
+    assert_eq!(cart.rb(0xA000), 0x00);
+}
+
+#[test]
+fn ram_size_override_must_be_a_header_size() {
+    let result = Cart::from_bytes_with_clock(
+        rom(0x1A, 2, 0x00),
+        None,
+        Box::new(FixedClock::default()),
+        Some(3000),
+    );
+
+    assert!(matches!(
+        result,
+        Err(CartError::InvalidRamSize { size: 3000 })
+    ));
+}
+
+#[test]
+fn mbc3_unmapped_rtc_register() {
+    let mut cart = cart(0x10, 2, 0x02); // MBC3+TIMER+RAM+BATTERY
+    cart.wb(0x0000, 0x0A);
+    cart.wb(0x4000, 0x0D);
+    cart.wb(0xA000, 0x12);
+
+    assert_eq!(cart.rb(0xA000), 0xFF);
+}
+
+#[test]
+fn mbc3_rtc_without_ram() {
+    let clock = FixedClock::default();
+    let rom = rom(0x0F, 2, 0x00); // MBC3+TIMER+BATTERY
+    let mut cart = Cart::from_bytes_with_clock(rom, None, Box::new(clock.clone()), None).unwrap();
+    cart.wb(0x0000, 0x0A);
+
+    clock.advance(5);
+    cart.wb(0x6000, 0x00);
+    cart.wb(0x6000, 0x01);
+    cart.wb(0x4000, 0x08);
+    assert_eq!(cart.rb(0xA000), 5);
+
+    cart.wb(0x4000, 0x00);
+    assert_eq!(cart.rb(0xA000), 0xFF);
+}
+
+#[test]
+fn ram_declared_without_size_reads_open_bus() {
+    // ROM only, MBC1+RAM+BATTERY, MBC3+RAM+BATTERY, MBC5+RAM+BATTERY, all with RAM id 0x00.
+    for cartridge_type in [0x00, 0x03, 0x13, 0x1B] {
+        let mut cart = cart(cartridge_type, 2, 0x00);
+        cart.wb(0x0000, 0x0A);
+        cart.wb(0xA000, 0x42);
+
+        assert_eq!(cart.rb(0xA000), 0xFF, "type {cartridge_type:#04x}");
+    }
+}
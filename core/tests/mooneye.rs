@@ -0,0 +1,46 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use core::gameboy::{Buttons, StopReason};
+
+// Ten seconds of emulated time, the acceptance ROMs report long before that.
+const FRAME_BUDGET: u32 = 10 * 60;
+
+// Registers B, C, D, E, H, L on success, the Fibonacci numbers. Failures load 0x42 instead.
+const PASS_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+const ACCEPTANCE: [&str; 10] = [
+    "bits/mem_oam.gb",
+    "bits/reg_f.gb",
+    "instr/daa.gb",
+    "oam_dma/basic.gb",
+    "oam_dma/reg_read.gb",
+    "timer/div_write.gb",
+    "timer/tim00.gb",
+    "timer/tim01.gb",
+    "timer/tim10.gb",
+    "timer/tim11.gb",
+];
+
+// Mooneye ROMs load their result into the registers, then execute LD B,B as a breakpoint.
+fn run(rel: &str) {
+    let mut gameboy = common::load(rel);
+
+    gameboy.set_ld_b_b_breakpoint(true);
+    let hit = (0..FRAME_BUDGET)
+        .any(|_| gameboy.run_frame(Buttons::NONE).unwrap() == StopReason::MagicBreakpoint);
+    assert!(hit, "{rel}: no LD B,B within the frame budget");
+
+    let cpu = gameboy.cpu_state();
+    let registers = [cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l];
+    assert_eq!(registers, PASS_SIGNATURE, "{rel} failed");
+}
+
+#[test]
+#[ignore = "needs the mooneye ROMs in tests/roms, see tests/roms/README.md"]
+fn acceptance() {
+    for rom in ACCEPTANCE {
+        run(&format!("mooneye/acceptance/{rom}"));
+    }
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "std")]
+
+use core::{
+    cart::Cart,
+    gameboy::{Buttons, GameBoy, Model},
+};
+
+// LD HL,<addr>; loop: INC HL; DEC HL; JR loop
+fn inc_dec_loop(model: Model, addr: u16) -> GameBoy {
+    let mut rom = vec![0u8; 0x8000];
+    let [lo, hi] = addr.to_le_bytes();
+    rom[0x100..0x107].copy_from_slice(&[0x21, lo, hi, 0x23, 0x2B, 0x18, 0xFC]);
+    let mut gameboy = GameBoy::new_with_model(Cart::from_bytes(rom, None).unwrap(), model);
+
+    for addr in 0xFE00..0xFEA0u16 {
+        gameboy.write_byte(addr, addr as u8);
+    }
+    gameboy.run_frames(2, Buttons::NONE).unwrap();
+    gameboy
+}
+
+fn oam_untouched(gameboy: &GameBoy) -> bool {
+    (0xFE00..0xFEA0u16).all(|addr| gameboy.read_byte(addr) == addr as u8)
+}
+
+#[test]
+fn dmg_corrupts_oam() {
+    let gameboy = inc_dec_loop(Model::DMG, 0xFE40);
+    assert!(!oam_untouched(&gameboy));
+    // The first row is never written.
+    assert!((0xFE00..0xFE08u16).all(|addr| gameboy.read_byte(addr) == addr as u8));
+}
+
+#[test]
+fn outside_oam_range() {
+    assert!(oam_untouched(&inc_dec_loop(Model::DMG, 0xFF00)));
+}
+
+#[test]
+fn cgb_is_fixed() {
+    assert!(oam_untouched(&inc_dec_loop(Model::CGB, 0xFE40)));
+}
@@ -0,0 +1,34 @@
+#![cfg(feature = "std")]
+
+use core::{
+    cart::Cart,
+    gameboy::{Buttons, GameBoy},
+};
+
+// LD A,$80; CALL $FF80; loop: JR loop
+const MAIN: [u8; 7] = [0x3E, 0x80, 0xCD, 0x80, 0xFF, 0x18, 0xFE];
+
+// The CPU can only reach HRAM during the transfer, so it waits there:
+// LDH ($46),A; LD A,40; wait: DEC A; JR NZ,wait; RET
+const HRAM_ROUTINE: [u8; 8] = [0xE0, 0x46, 0x3E, 40, 0x3D, 0x20, 0xFD, 0xC9];
+
+#[test]
+fn copies_vram_while_the_ppu_draws() {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x107].copy_from_slice(&MAIN);
+    let mut gameboy = GameBoy::new(Cart::from_bytes(rom, None).unwrap());
+
+    for (i, &byte) in HRAM_ROUTINE.iter().enumerate() {
+        gameboy.write_byte(0xFF80 + i as u16, byte);
+    }
+    for i in 0..0xA0u16 {
+        gameboy.write_byte(0x8000 + i, i as u8 + 1);
+    }
+
+    // 160 M-cycles span more than one line, so some bytes are copied during mode 3.
+    gameboy.run_frame(Buttons::NONE).unwrap();
+
+    for i in 0..0xA0u16 {
+        assert_eq!(gameboy.read_byte(0xFE00 + i), i as u8 + 1, "OAM byte {i}");
+    }
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "std")]
+
+use core::{
+    cart::Cart,
+    gameboy::{Buttons, GameBoy},
+};
+
+const SCREEN_W: usize = 160;
+
+// Idles in a JR loop, leaving the PPU state to the test.
+fn idle_gameboy() -> GameBoy {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+    GameBoy::new(Cart::from_bytes(rom, None).unwrap())
+}
+
+// Tile 1, every pixel color 3.
+fn solid_tile(gameboy: &mut GameBoy) {
+    for addr in 0x8010..0x8020 {
+        gameboy.write_byte(addr, 0xFF);
+    }
+}
+
+#[test]
+fn ten_sprites_per_line() {
+    let mut gameboy = idle_gameboy();
+    solid_tile(&mut gameboy);
+
+    // 12 sprites on lines 0-7, side by side without overlapping.
+    for sprite in 0..12u16 {
+        let oam = 0xFE00 + sprite * 4;
+        gameboy.write_byte(oam, 16);
+        gameboy.write_byte(oam + 1, 8 + sprite as u8 * 12);
+        gameboy.write_byte(oam + 2, 1);
+        gameboy.write_byte(oam + 3, 0);
+    }
+    gameboy.write_byte(0xFF48, 0xE4); // OBP0
+    gameboy.write_byte(0xFF40, 0x82); // LCD and OBJ on, BG off
+    gameboy.run_frames(2, Buttons::NONE).unwrap();
+
+    let line = &gameboy.frame_indices()[4 * SCREEN_W..5 * SCREEN_W];
+    for sprite in 0..12 {
+        let x = sprite * 12;
+        let drawn = line[x..x + 8].iter().all(|&shade| shade == 3);
+        assert_eq!(drawn, sprite < 10, "sprite {sprite}");
+    }
+}
+
+#[test]
+fn bg_hides_front_object_over_back_object() {
+    let mut gameboy = idle_gameboy();
+    solid_tile(&mut gameboy);
+    // Tile 0, the whole BG, every pixel color 1.
+    for addr in (0x8000..0x8010).step_by(2) {
+        gameboy.write_byte(addr, 0xFF);
+    }
+
+    // Both objects on the same spot, the first in OAM is in front and behind the BG.
+    for (sprite, attr) in [(0u16, 0x80), (1, 0x00)] {
+        let oam = 0xFE00 + sprite * 4;
+        gameboy.write_byte(oam, 16);
+        gameboy.write_byte(oam + 1, 8);
+        gameboy.write_byte(oam + 2, 1);
+        gameboy.write_byte(oam + 3, attr);
+    }
+    gameboy.write_byte(0xFF47, 0xE4); // BGP
+    gameboy.write_byte(0xFF48, 0xE4); // OBP0
+    gameboy.write_byte(0xFF40, 0x93); // LCD, OBJ and BG on, tiles at 0x8000
+    gameboy.run_frames(2, Buttons::NONE).unwrap();
+
+    let line = &gameboy.frame_indices()[4 * SCREEN_W..5 * SCREEN_W];
+    assert_eq!(line[..8], [1; 8]);
+}